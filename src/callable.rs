@@ -0,0 +1,91 @@
+use std::fmt::{self, Debug, Display, Formatter};
+use std::rc::Rc;
+
+use crate::{
+    capabilities::Capability, environment::Environment, error::RuntimeError, expr::Literal,
+    stmt::Stmt, token::Token,
+};
+
+/// A value that can be invoked with a call expression, such as `f(1, 2)`.
+#[derive(Clone)]
+pub enum Callable {
+    /// A function written in Lox, created by a `fun` declaration or a lambda expression.
+    Function(Rc<Function>),
+    /// A function implemented in Rust and exposed to scripts as a global.
+    Native(Rc<NativeFunction>),
+}
+
+/// A user-defined function together with the environment it closes over.
+pub struct Function {
+    /// The declared name, or `None` for lambdas.
+    pub name: Option<String>,
+    /// The parameter names.
+    pub params: Vec<Token>,
+    /// The statements making up the function body.
+    pub body: Rc<Vec<Stmt>>,
+    /// The scopes visible where the function was created.
+    pub closure: Environment,
+}
+
+/// A built-in function implemented in Rust.
+pub struct NativeFunction {
+    /// The global name the function is bound to.
+    pub name: &'static str,
+    /// The number of arguments the function expects.
+    pub arity: usize,
+    /// The capability a script needs to call the function, if any.
+    pub capability: Option<Capability>,
+    /// The implementation, receiving the evaluated arguments.
+    pub function: fn(&[Literal]) -> Result<Literal, RuntimeError>,
+}
+
+impl Function {
+    /// The name the function has in profiles and traces: its declared name, or
+    /// `<lambda:N>` with the line its body starts on.
+    pub(crate) fn report_name(&self) -> String {
+        match (&self.name, self.body.iter().find_map(Stmt::line)) {
+            (Some(name), _) => name.clone(),
+            (None, Some(line)) => format!("<lambda:{}>", line),
+            (None, None) => "<lambda>".to_string(),
+        }
+    }
+}
+
+impl Callable {
+    /// Returns the number of arguments the callable expects.
+    pub fn arity(&self) -> usize {
+        match self {
+            Callable::Function(function) => function.params.len(),
+            Callable::Native(native) => native.arity,
+        }
+    }
+}
+
+impl PartialEq for Callable {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Callable::Function(l), Callable::Function(r)) => Rc::ptr_eq(l, r),
+            (Callable::Native(l), Callable::Native(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Callable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Callable::Function(function) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Callable::Native(native) => write!(f, "<native fn {}>", native.name),
+        }
+    }
+}
+
+// A closure can capture the scope it is stored in, so the derived `Debug` would recurse.
+impl Debug for Callable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
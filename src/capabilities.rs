@@ -0,0 +1,88 @@
+//! Permissions for the native functions that reach outside the interpreter.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Formatter},
+};
+
+/// A group of native functions that scripts may be allowed to call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Capability {
+    /// Reading and writing files: `read_file` and `write_file`.
+    FileIo,
+    /// Reading environment variables: `getenv`.
+    Env,
+    /// Reading the clock: `clock`.
+    Time,
+    /// Random numbers: `random`.
+    Random,
+    /// Reading standard input: `read_line`.
+    Stdin,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::FileIo,
+        Capability::Env,
+        Capability::Time,
+        Capability::Random,
+        Capability::Stdin,
+    ];
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::FileIo => "file I/O",
+            Capability::Env => "environment variables",
+            Capability::Time => "time",
+            Capability::Random => "randomness",
+            Capability::Stdin => "stdin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The capabilities a script may use. Natives needing any other capability are still
+/// defined, but calling them is a runtime error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    allowed: BTreeSet<Capability>,
+}
+
+impl Default for Capabilities {
+    /// Every capability, as for scripts run from the command line.
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl Capabilities {
+    /// Allows every capability.
+    pub fn all() -> Self {
+        Capabilities {
+            allowed: Capability::ALL.into_iter().collect(),
+        }
+    }
+
+    /// Allows nothing, for running untrusted scripts.
+    pub fn none() -> Self {
+        Capabilities {
+            allowed: BTreeSet::new(),
+        }
+    }
+
+    pub fn allow(mut self, capability: Capability) -> Self {
+        self.allowed.insert(capability);
+        self
+    }
+
+    pub fn deny(mut self, capability: Capability) -> Self {
+        self.allowed.remove(&capability);
+        self
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.allowed.contains(&capability)
+    }
+}
@@ -0,0 +1,492 @@
+/// What the `rlox` binary was asked to do.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Run or inspect a program.
+    Run(Options),
+    /// Format script files in place, or only check that they are formatted.
+    Fmt { check: bool, files: Vec<String> },
+    /// Lint script files, with rule levels from an optional config file.
+    Lint {
+        config: Option<String>,
+        files: Vec<String>,
+    },
+    /// Serve the Language Server Protocol over stdin and stdout.
+    Lsp,
+    /// Serve the Debug Adapter Protocol over stdin and stdout.
+    Dap,
+    /// Run the test functions in the test files under the given paths, optionally
+    /// writing their coverage to an LCOV file.
+    Test {
+        paths: Vec<String>,
+        coverage: Option<String>,
+    },
+}
+
+/// What the `rlox` binary should do with its input.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Run the program.
+    Run,
+    /// Print the tokens scanned from the program.
+    Tokens,
+    /// Print the parsed syntax tree of the program.
+    Ast,
+    /// Print the parsed syntax tree of the program as JSON.
+    JsonAst,
+    /// Run the program under the debugger, driven by a command prompt.
+    Debug,
+}
+
+/// Where the program comes from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    /// An interactive REPL session.
+    Prompt,
+    /// A script file.
+    File(String),
+    /// Code passed with `-e`.
+    Inline(String),
+}
+
+/// Where to write an execution trace.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TraceOutput {
+    Stderr,
+    File(String),
+}
+
+/// Parsed command line arguments.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub mode: Mode,
+    pub input: Input,
+    /// The LCOV file to write the coverage of the program to.
+    pub coverage: Option<String>,
+    /// The file to write the profile of the program to, as folded stacks.
+    pub profile: Option<String>,
+    /// Where to write the trace of the program, if it is traced.
+    pub trace: Option<TraceOutput>,
+}
+
+pub const USAGE: &str = "\
+Usage: rlox [options] [script]
+       rlox fmt [--check] <script>...
+       rlox lint [--config <file>] <script>...
+       rlox lsp
+       rlox dap
+       rlox test [--coverage <file>] [path]...
+
+Options:
+  -e <code>   Run <code> instead of a script.
+  --tokens    Print every token instead of running the program.
+  --ast       Print the syntax tree instead of running the program.
+  --json-ast  Print the syntax tree as JSON instead of running the program.
+  --debug     Run the program in the debugger, pausing before the first
+              statement. Type 'help' at the (debug) prompt for commands.
+  --coverage <file>
+              Write the lines and branches the script ran to <file> in the
+              LCOV format, and print a summary to stderr.
+  --profile <file>
+              Time every function and line, write the call stacks to <file>
+              in the folded format of flame graph tools, and print a report
+              to stderr.
+  --trace     Log every statement, variable definition and assignment, and
+              function call and return to stderr.
+  --trace-file <file>
+              Write the log of --trace to <file> instead.
+  -h, --help  Print this help.
+
+Commands:
+  fmt         Format scripts in place. With --check, only list the scripts
+              that are not formatted and fail if there are any.
+  lint        Report likely mistakes in scripts, and fail if any is an error.
+              Rule levels are read from --config, or from .rloxlint in the
+              current directory if it exists.
+  lsp         Run a language server over stdin and stdout.
+  dap         Run a debug adapter over stdin and stdout.
+  test        Run every test_* function in the *_test.lox files under each
+              path, or under the current directory, and fail if any fails.
+              With --coverage, write the coverage of the tests to a file.";
+
+/// Parses the command line arguments, excluding the program name.
+///
+/// Returns `Ok(None)` when help was requested, and an error message for invalid usage.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Command>, String> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("fmt") => {
+            args.next();
+            parse_fmt_args(args)
+        }
+        Some("lint") => {
+            args.next();
+            parse_lint_args(args)
+        }
+        Some("test") => {
+            args.next();
+            let mut paths = Vec::new();
+            let mut coverage = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "-h" | "--help" => return Ok(None),
+                    "--coverage" => coverage = Some(file_argument(&arg, &mut args)?),
+                    flag if flag.starts_with('-') => {
+                        return Err(format!("Unknown option: {}", flag));
+                    }
+                    _ => paths.push(arg),
+                }
+            }
+            if paths.is_empty() {
+                paths.push(".".to_string());
+            }
+            Ok(Some(Command::Test { paths, coverage }))
+        }
+        Some("lsp") | Some("dap") => {
+            let name = args.next().unwrap_or_default();
+            match args.next() {
+                None if name == "lsp" => Ok(Some(Command::Lsp)),
+                None => Ok(Some(Command::Dap)),
+                Some(arg) if arg == "-h" || arg == "--help" => Ok(None),
+                Some(arg) => Err(format!("Unexpected argument to {}: {}", name, arg)),
+            }
+        }
+        _ => Ok(parse_run_args(args)?.map(Command::Run)),
+    }
+}
+
+fn parse_fmt_args(args: impl Iterator<Item = String>) -> Result<Option<Command>, String> {
+    let mut check = false;
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--check" => check = true,
+            flag if flag.starts_with('-') => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        return Err("fmt needs at least one script.".to_string());
+    }
+    Ok(Some(Command::Fmt { check, files }))
+}
+
+fn parse_lint_args(mut args: impl Iterator<Item = String>) -> Result<Option<Command>, String> {
+    let mut config = None;
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--config" => match args.next() {
+                Some(file) => config = Some(file),
+                None => return Err("Missing file after --config.".to_string()),
+            },
+            flag if flag.starts_with('-') => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        return Err("lint needs at least one script.".to_string());
+    }
+    Ok(Some(Command::Lint { config, files }))
+}
+
+/// Returns the file named after the option `flag`.
+fn file_argument(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing file after {}.", flag))
+}
+
+fn parse_run_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut mode = Mode::Run;
+    let mut input = Input::Prompt;
+    let mut coverage = None;
+    let mut profile = None;
+    let mut trace = None;
+
+    while let Some(arg) = args.next() {
+        let next_input = match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--tokens" | "--ast" | "--json-ast" | "--debug" => {
+                if mode != Mode::Run {
+                    return Err(
+                        "Only one of --tokens, --ast, --json-ast and --debug may be given."
+                            .to_string(),
+                    );
+                }
+                mode = match arg.as_str() {
+                    "--tokens" => Mode::Tokens,
+                    "--ast" => Mode::Ast,
+                    "--json-ast" => Mode::JsonAst,
+                    _ => Mode::Debug,
+                };
+                continue;
+            }
+            "--coverage" => {
+                coverage = Some(file_argument(&arg, &mut args)?);
+                continue;
+            }
+            "--profile" => {
+                profile = Some(file_argument(&arg, &mut args)?);
+                continue;
+            }
+            "--trace" | "--trace-file" => {
+                if trace.is_some() {
+                    return Err("Only one of --trace and --trace-file may be given.".to_string());
+                }
+                trace = Some(match arg.as_str() {
+                    "--trace" => TraceOutput::Stderr,
+                    _ => TraceOutput::File(file_argument(&arg, &mut args)?),
+                });
+                continue;
+            }
+            "-e" => match args.next() {
+                Some(code) => Input::Inline(code),
+                None => return Err("Missing code after -e.".to_string()),
+            },
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+            _ => Input::File(arg),
+        };
+
+        if input != Input::Prompt {
+            return Err("Only one script or -e snippet may be given.".to_string());
+        }
+        input = next_input;
+    }
+
+    if mode != Mode::Run && input == Input::Prompt {
+        return Err(
+            "--tokens, --ast, --json-ast and --debug need a script or -e snippet.".to_string(),
+        );
+    }
+
+    if coverage.is_some() && !matches!((&mode, &input), (Mode::Run, Input::File(_))) {
+        return Err(
+            "--coverage needs a script, and can't be combined with other modes.".to_string(),
+        );
+    }
+
+    for (flag, given) in [
+        ("--profile", profile.is_some()),
+        ("--trace", trace.is_some()),
+    ] {
+        if given && (mode != Mode::Run || input == Input::Prompt) {
+            return Err(format!(
+                "{} needs a script or -e snippet, and can't be combined with other modes.",
+                flag
+            ));
+        }
+    }
+
+    Ok(Some(Options {
+        mode,
+        input,
+        coverage,
+        profile,
+        trace,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Command>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let test_cases = vec![
+            (vec![], Mode::Run, Input::Prompt),
+            (
+                vec!["script.lox"],
+                Mode::Run,
+                Input::File("script.lox".to_string()),
+            ),
+            (
+                vec!["--tokens", "script.lox"],
+                Mode::Tokens,
+                Input::File("script.lox".to_string()),
+            ),
+            (
+                vec!["-e", "print 1;", "--ast"],
+                Mode::Ast,
+                Input::Inline("print 1;".to_string()),
+            ),
+            (
+                vec!["--json-ast", "script.lox"],
+                Mode::JsonAst,
+                Input::File("script.lox".to_string()),
+            ),
+            (
+                vec!["--debug", "script.lox"],
+                Mode::Debug,
+                Input::File("script.lox".to_string()),
+            ),
+        ];
+
+        for (args, mode, input) in test_cases {
+            assert_eq!(
+                parse(&args),
+                Ok(Some(Command::Run(Options {
+                    mode,
+                    input,
+                    coverage: None,
+                    profile: None,
+                    trace: None
+                }))),
+                "Failed on args: {:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_instrumentation_args() {
+        assert_eq!(
+            parse(&["--coverage", "lcov.info", "script.lox"]),
+            Ok(Some(Command::Run(Options {
+                mode: Mode::Run,
+                input: Input::File("script.lox".to_string()),
+                coverage: Some("lcov.info".to_string()),
+                profile: None,
+                trace: None,
+            })))
+        );
+        assert_eq!(
+            parse(&["-e", "print 1;", "--profile", "out.folded"]),
+            Ok(Some(Command::Run(Options {
+                mode: Mode::Run,
+                input: Input::Inline("print 1;".to_string()),
+                coverage: None,
+                profile: Some("out.folded".to_string()),
+                trace: None,
+            })))
+        );
+        assert_eq!(
+            parse(&["--trace", "a.lox"]),
+            Ok(Some(Command::Run(Options {
+                mode: Mode::Run,
+                input: Input::File("a.lox".to_string()),
+                coverage: None,
+                profile: None,
+                trace: Some(TraceOutput::Stderr),
+            })))
+        );
+        assert_eq!(
+            parse(&["--trace-file", "trace.log", "-e", "print 1;"]),
+            Ok(Some(Command::Run(Options {
+                mode: Mode::Run,
+                input: Input::Inline("print 1;".to_string()),
+                coverage: None,
+                profile: None,
+                trace: Some(TraceOutput::File("trace.log".to_string())),
+            })))
+        );
+        for args in [
+            vec!["--coverage", "lcov.info"],
+            vec!["--coverage", "lcov.info", "-e", "print 1;"],
+            vec!["--coverage", "lcov.info", "--ast", "a.lox"],
+            vec!["a.lox", "--coverage"],
+            vec!["--profile", "out.folded"],
+            vec!["--profile", "out.folded", "--debug", "a.lox"],
+            vec!["a.lox", "--profile"],
+            vec!["--trace"],
+            vec!["--trace", "--trace-file", "trace.log", "a.lox"],
+            vec!["--trace", "--tokens", "a.lox"],
+            vec!["a.lox", "--trace-file"],
+        ] {
+            assert!(parse(&args).is_err(), "Failed on args: {:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_parse_fmt_args() {
+        assert_eq!(
+            parse(&["fmt", "a.lox", "b.lox"]),
+            Ok(Some(Command::Fmt {
+                check: false,
+                files: vec!["a.lox".to_string(), "b.lox".to_string()],
+            }))
+        );
+        assert_eq!(
+            parse(&["fmt", "--check", "a.lox"]),
+            Ok(Some(Command::Fmt {
+                check: true,
+                files: vec!["a.lox".to_string()],
+            }))
+        );
+        assert_eq!(parse(&["fmt", "--help"]), Ok(None));
+        assert!(parse(&["fmt"]).is_err());
+        assert!(parse(&["fmt", "--tokens", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_lint_args() {
+        assert_eq!(
+            parse(&["lint", "a.lox", "--config", "lint.conf"]),
+            Ok(Some(Command::Lint {
+                config: Some("lint.conf".to_string()),
+                files: vec!["a.lox".to_string()],
+            }))
+        );
+        assert!(parse(&["lint"]).is_err());
+        assert!(parse(&["lint", "a.lox", "--config"]).is_err());
+        assert_eq!(parse(&["lsp"]), Ok(Some(Command::Lsp)));
+        assert!(parse(&["lsp", "a.lox"]).is_err());
+        assert_eq!(parse(&["dap"]), Ok(Some(Command::Dap)));
+        assert_eq!(
+            parse(&["test"]),
+            Ok(Some(Command::Test {
+                paths: vec![".".to_string()],
+                coverage: None
+            }))
+        );
+        assert_eq!(
+            parse(&["test", "a_test.lox", "tests"]),
+            Ok(Some(Command::Test {
+                paths: vec!["a_test.lox".to_string(), "tests".to_string()],
+                coverage: None
+            }))
+        );
+        assert_eq!(
+            parse(&["test", "--coverage", "lcov.info", "tests"]),
+            Ok(Some(Command::Test {
+                paths: vec!["tests".to_string()],
+                coverage: Some("lcov.info".to_string())
+            }))
+        );
+        assert!(parse(&["test", "--coverage"]).is_err());
+        assert!(parse(&["test", "--bogus"]).is_err());
+        assert!(parse(&["dap", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        assert_eq!(parse(&["--help", "script.lox"]), Ok(None));
+        for args in [
+            vec!["a.lox", "b.lox"],
+            vec!["-e"],
+            vec!["-e", "1;", "a.lox"],
+            vec!["--tokens", "--ast", "a.lox"],
+            vec!["--ast", "--json-ast", "a.lox"],
+            vec!["--tokens"],
+            vec!["--debug"],
+            vec!["--debug", "--ast", "a.lox"],
+            vec!["--bogus"],
+        ] {
+            assert!(parse(&args).is_err(), "Failed on args: {:?}", args);
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Line and branch coverage of a program, reported in the LCOV format.
+//!
+//! A [`Coverage`] is built from the syntax tree before the program runs, so that code
+//! which never runs is reported too. The interpreter then counts every statement it
+//! executes and every branch it takes. Branch points are `if` statements, `?:`
+//! conditionals and the `and`, `or` and `??` operators, and are identified by the
+//! address of their syntax tree node, so the tree must outlive the run.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+};
+
+use crate::{expr::Expr, stmt::Stmt, token::Token};
+
+/// A place where execution takes one of two ways.
+#[derive(Debug)]
+struct BranchPoint {
+    line: usize,
+    /// How often each way was taken: for `if` and `?:` the then and else branches, for
+    /// logical operators evaluating the right operand and short-circuiting.
+    taken: [u64; 2],
+}
+
+#[derive(Debug, Default)]
+pub struct Coverage {
+    /// How often each line with a statement ran.
+    lines: BTreeMap<usize, u64>,
+    /// Branch points in source order.
+    branches: Vec<BranchPoint>,
+    /// The branch point of each syntax tree node, by address.
+    branch_index: HashMap<usize, usize>,
+}
+
+/// Hit counts for a summary.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub lines_found: usize,
+    pub lines_hit: usize,
+    pub branches_found: usize,
+    pub branches_hit: usize,
+}
+
+impl Coverage {
+    /// Finds every line and branch point of a program, none of them hit yet.
+    pub fn new(stmts: &[Stmt]) -> Self {
+        let mut coverage = Coverage::default();
+        for stmt in stmts {
+            coverage.add_stmt(stmt);
+        }
+        coverage
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt) {
+        if !matches!(stmt, Stmt::Block(_)) {
+            if let Some(line) = stmt.line() {
+                self.lines.entry(line).or_default();
+            }
+        }
+        match stmt {
+            Stmt::Expr(expr) => self.add_expr(expr),
+            Stmt::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.add_branch_point(condition, keyword.line);
+                self.add_expr(condition);
+                self.add_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.add_stmt(else_branch);
+                }
+            }
+            Stmt::Print { expression, .. } => self.add_expr(expression),
+            Stmt::Var { initializer, .. } => self.add_expr(initializer),
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.add_expr(condition);
+                self.add_stmt(body);
+            }
+            Stmt::Block(stmts) => stmts.iter().for_each(|stmt| self.add_stmt(stmt)),
+            Stmt::Function { body, .. } => body.iter().for_each(|stmt| self.add_stmt(stmt)),
+            Stmt::Return { value, .. } => self.add_expr(value),
+        }
+    }
+
+    fn add_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { value, .. } => self.add_expr(value),
+            Expr::Binary { left, right, .. } => {
+                self.add_expr(left);
+                self.add_expr(right);
+            }
+            Expr::Grouping { expression } => self.add_expr(expression),
+            Expr::Literal(_) | Expr::Variable { .. } => {}
+            Expr::Unary { right, .. } => self.add_expr(right),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.add_expr(left);
+                self.add_branch_point(operator, operator.line);
+                self.add_expr(right);
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.add_expr(condition);
+                let line = condition.line().unwrap_or_default();
+                self.add_branch_point(condition.as_ref(), line);
+                self.add_expr(then_branch);
+                self.add_expr(else_branch);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.add_expr(callee);
+                arguments
+                    .iter()
+                    .for_each(|argument| self.add_expr(argument));
+            }
+            Expr::Lambda { body, .. } => body.iter().for_each(|stmt| self.add_stmt(stmt)),
+        }
+    }
+
+    fn add_branch_point<T>(&mut self, node: &T, line: usize) {
+        self.branch_index
+            .insert(node as *const T as usize, self.branches.len());
+        self.branches.push(BranchPoint {
+            line,
+            taken: [0, 0],
+        });
+    }
+
+    /// Counts a statement about to run.
+    pub(crate) fn record_stmt(&mut self, stmt: &Stmt) {
+        if matches!(stmt, Stmt::Block(_)) {
+            return;
+        }
+        // Statements outside the syntax tree the coverage was built from don't count.
+        if let Some(hits) = stmt.line().and_then(|line| self.lines.get_mut(&line)) {
+            *hits += 1;
+        }
+    }
+
+    fn record_branch(&mut self, key: usize, first: bool) {
+        if let Some(&index) = self.branch_index.get(&key) {
+            self.branches[index].taken[usize::from(!first)] += 1;
+        }
+    }
+
+    /// Counts the way taken at an `if` statement or `?:` conditional.
+    pub(crate) fn record_condition(&mut self, condition: &Expr, then_branch: bool) {
+        self.record_branch(condition as *const Expr as usize, then_branch);
+    }
+
+    /// Counts whether a logical operator evaluated its right operand.
+    pub(crate) fn record_logical(&mut self, operator: &Token, evaluated_right: bool) {
+        self.record_branch(operator as *const Token as usize, evaluated_right);
+    }
+
+    pub fn summary(&self) -> Summary {
+        Summary {
+            lines_found: self.lines.len(),
+            lines_hit: self.lines.values().filter(|hits| **hits > 0).count(),
+            branches_found: self.branches.len() * 2,
+            branches_hit: self
+                .branches
+                .iter()
+                .flat_map(|branch| branch.taken)
+                .filter(|taken| *taken > 0)
+                .count(),
+        }
+    }
+
+    /// The lines with statements that never ran, as ranges of consecutive lines.
+    pub fn uncovered_lines(&self) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut previous = None;
+        for (&line, &hits) in &self.lines {
+            if hits > 0 {
+                previous = Some(line);
+                continue;
+            }
+            match ranges.last_mut() {
+                // Extend the range when no line ran in between.
+                Some(range) if previous == Some(range.1) => range.1 = line,
+                _ => ranges.push((line, line)),
+            }
+            previous = Some(line);
+        }
+        ranges
+    }
+
+    /// Writes one LCOV record for the source file at `path`.
+    pub fn write_lcov(&self, path: &str, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "TN:")?;
+        writeln!(output, "SF:{}", path)?;
+
+        // Branch points are numbered per line, in source order.
+        let mut blocks: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut records = Vec::new();
+        for branch in &self.branches {
+            let block = blocks.entry(branch.line).or_default();
+            records.push((branch.line, *block, branch.taken));
+            *block += 1;
+        }
+        records.sort_by_key(|(line, block, _)| (*line, *block));
+        for (line, block, taken) in records {
+            let ran = self.lines.get(&line).is_some_and(|hits| *hits > 0);
+            for (branch, count) in taken.iter().enumerate() {
+                match ran || *count > 0 {
+                    true => writeln!(output, "BRDA:{},{},{},{}", line, block, branch, count)?,
+                    false => writeln!(output, "BRDA:{},{},{},-", line, block, branch)?,
+                }
+            }
+        }
+
+        let summary = self.summary();
+        writeln!(output, "BRF:{}", summary.branches_found)?;
+        writeln!(output, "BRH:{}", summary.branches_hit)?;
+        for (line, hits) in &self.lines {
+            writeln!(output, "DA:{},{}", line, hits)?;
+        }
+        writeln!(output, "LF:{}", summary.lines_found)?;
+        writeln!(output, "LH:{}", summary.lines_hit)?;
+        writeln!(output, "end_of_record")
+    }
+}
+
+impl Summary {
+    pub fn add(&mut self, other: Summary) {
+        self.lines_found += other.lines_found;
+        self.lines_hit += other.lines_hit;
+        self.branches_found += other.branches_found;
+        self.branches_hit += other.branches_hit;
+    }
+}
+
+fn percent(hit: usize, found: usize) -> f64 {
+    match found {
+        0 => 100.0,
+        _ => hit as f64 * 100.0 / found as f64,
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lines {:.1}% ({}/{}), branches {:.1}% ({}/{})",
+            percent(self.lines_hit, self.lines_found),
+            self.lines_hit,
+            self.lines_found,
+            percent(self.branches_hit, self.branches_found),
+            self.branches_hit,
+            self.branches_found
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Environment, interpreter::Interpreter, parser::Parser, scanner::Scanner,
+    };
+
+    const SOURCE: &str = "var x = 1;
+if (x > 0) {
+  print \"positive\";
+} else {
+  print \"negative\";
+}
+fun unused() {
+  return nil;
+}
+var y = x > 5 or x == 1;
+var z = nil ?? (x < 0 ? 1 : 2);
+";
+
+    fn run(source: &str) -> Coverage {
+        let mut scanner = Scanner::new(source);
+        let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+        interpreter.set_coverage(Coverage::new(&stmts));
+        interpreter.interpret(&stmts).unwrap();
+        interpreter.take_coverage().unwrap()
+    }
+
+    #[test]
+    fn test_lcov() {
+        let coverage = run(SOURCE);
+        let mut lcov = Vec::new();
+        coverage.write_lcov("test.lox", &mut lcov).unwrap();
+        assert_eq!(
+            String::from_utf8(lcov).unwrap(),
+            "TN:
+SF:test.lox
+BRDA:2,0,0,1
+BRDA:2,0,1,0
+BRDA:10,0,0,1
+BRDA:10,0,1,0
+BRDA:11,0,0,1
+BRDA:11,0,1,0
+BRDA:11,1,0,0
+BRDA:11,1,1,1
+BRF:8
+BRH:4
+DA:1,1
+DA:2,1
+DA:3,1
+DA:5,0
+DA:7,1
+DA:8,0
+DA:10,1
+DA:11,1
+LF:8
+LH:6
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let coverage = run(SOURCE);
+        assert_eq!(
+            coverage.summary().to_string(),
+            "lines 75.0% (6/8), branches 50.0% (4/8)"
+        );
+        assert_eq!(coverage.uncovered_lines(), vec![(5, 5), (8, 8)]);
+
+        let coverage = run("var a = 1;\nif (a < 0) {\n  print 1;\n  print 2;\n}\nprint 3;\n");
+        assert_eq!(coverage.uncovered_lines(), vec![(3, 4)]);
+    }
+
+    #[test]
+    fn test_counts_accumulate() {
+        let coverage = run("for (var i = 0; i < 3; i = i + 1) {\n  print i;\n}\n");
+        let mut lcov = Vec::new();
+        coverage.write_lcov("loop.lox", &mut lcov).unwrap();
+        let lcov = String::from_utf8(lcov).unwrap();
+        // The declaration, the loop and three increments share the first line.
+        assert!(lcov.contains("DA:1,5\nDA:2,3\n"), "{}", lcov);
+        assert!(lcov.contains("BRF:0\n"), "{}", lcov);
+    }
+}
@@ -0,0 +1,464 @@
+//! A Debug Adapter Protocol server for Lox, spoken over stdin and stdout.
+//!
+//! The adapter runs the launched program itself, on the same thread that reads the
+//! client's messages: while the program runs, requests wait until it pauses or ends.
+//! Lines and columns are 1-based, and there is a single thread with id 1.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    debugger::{self, Action, Debugger, Frontend, Pause, Reason},
+    environment::Environment,
+    expr::Literal,
+    interpreter::Interpreter,
+    lsp::{read_message, write_message},
+    parser::Parser,
+    scanner::Scanner,
+};
+
+const THREAD_ID: u64 = 1;
+
+/// The client connection, shared by the adapter, the paused program and its output.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: u64,
+}
+
+type Shared = Rc<RefCell<Connection>>;
+
+impl Connection {
+    fn read(&mut self) -> io::Result<Option<Value>> {
+        read_message(&mut self.input)
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+/// The program's standard output, sent to the client as `output` events, one per line.
+struct ProgramOutput {
+    connection: Shared,
+    line: Vec<u8>,
+}
+
+impl Write for ProgramOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(end) = self.line.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = self.line.drain(..=end).collect();
+            output_event(&self.connection, "stdout", &String::from_utf8_lossy(&lines))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            let rest: Vec<u8> = self.line.drain(..).collect();
+            output_event(&self.connection, "stdout", &String::from_utf8_lossy(&rest))?;
+        }
+        Ok(())
+    }
+}
+
+fn output_event(connection: &Shared, category: &str, output: &str) -> io::Result<()> {
+    connection
+        .borrow_mut()
+        .event("output", json!({ "category": category, "output": output }))
+}
+
+/// The program to debug, from the `launch` request.
+struct Launch {
+    path: String,
+    source: String,
+    stop_on_entry: bool,
+}
+
+/// Serves one debug session from `input`: configures and launches the program, runs it
+/// under the debugger, and returns once the client disconnects or the input ends.
+pub fn run(input: impl BufRead + 'static, output: impl Write + 'static) -> io::Result<()> {
+    let connection = Rc::new(RefCell::new(Connection {
+        input: Box::new(input),
+        output: Box::new(output),
+        seq: 0,
+    }));
+    let mut breakpoints = BTreeSet::new();
+    let mut launch = None;
+    let mut configured = false;
+
+    // VS Code sends `launch` before the configuration requests, so the program only
+    // starts once both have arrived.
+    while launch.is_none() || !configured {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let arguments = &request["arguments"];
+        let result = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                connection
+                    .borrow_mut()
+                    .respond(&request, Ok(capabilities()))?;
+                connection.borrow_mut().event("initialized", json!({}))?;
+                continue;
+            }
+            "setBreakpoints" => Ok(set_breakpoints(&mut breakpoints, arguments)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                configured = true;
+                Ok(json!({}))
+            }
+            "launch" => read_launch(arguments).map(|program| {
+                launch = Some(program);
+                json!({})
+            }),
+            "threads" => Ok(threads()),
+            "disconnect" | "terminate" => {
+                return connection.borrow_mut().respond(&request, Ok(json!({})));
+            }
+            command => Err(format!("Unsupported request '{}'.", command)),
+        };
+        connection.borrow_mut().respond(&request, result)?;
+    }
+
+    let launch = launch.expect("the loop above waits for a launch request");
+    let Some(exit_code) = debug_program(&connection, &launch, breakpoints)? else {
+        // The client disconnected while the program was paused.
+        return Ok(());
+    };
+    {
+        let mut connection = connection.borrow_mut();
+        connection.event("exited", json!({ "exitCode": exit_code }))?;
+        connection.event("terminated", json!({}))?;
+    }
+
+    // The client still owns the session until it disconnects.
+    loop {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let result = match request["command"].as_str().unwrap_or_default() {
+            "disconnect" => return connection.borrow_mut().respond(&request, Ok(json!({}))),
+            "threads" => Ok(threads()),
+            "setBreakpoints" => Ok(set_breakpoints(&mut BTreeSet::new(), &request["arguments"])),
+            _ => Err("The program has terminated.".to_string()),
+        };
+        connection.borrow_mut().respond(&request, result)?;
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsSetVariable": true,
+        "supportsEvaluateForHovers": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn threads() -> Value {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+fn read_launch(arguments: &Value) -> Result<Launch, String> {
+    let path = arguments["program"]
+        .as_str()
+        .ok_or("Missing 'program' to launch.")?;
+    let source = fs::read_to_string(path).map_err(|_| format!("Could not open: {}", path))?;
+    Ok(Launch {
+        path: path.to_string(),
+        source,
+        stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+    })
+}
+
+/// Replaces the breakpoints with the lines in a `setBreakpoints` request. There is
+/// only one source file, so the request's source is not checked.
+fn set_breakpoints(breakpoints: &mut BTreeSet<usize>, arguments: &Value) -> Value {
+    let lines: Vec<u64> = arguments["breakpoints"]
+        .as_array()
+        .map(|breakpoints| {
+            breakpoints
+                .iter()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    breakpoints.clear();
+    breakpoints.extend(lines.iter().map(|line| *line as usize));
+    let verified: Vec<Value> = lines
+        .iter()
+        .map(|line| json!({ "verified": true, "line": line }))
+        .collect();
+    json!({ "breakpoints": verified })
+}
+
+/// Runs the launched program under the debugger and returns its exit code, which
+/// follows the `rlox` binary's, or `None` if the client disconnected while it was
+/// paused.
+fn debug_program(
+    connection: &Shared,
+    launch: &Launch,
+    breakpoints: BTreeSet<usize>,
+) -> io::Result<Option<i64>> {
+    let mut scanner = Scanner::new(&launch.source);
+    let tokens = scanner.scan_tokens().clone();
+    for error in scanner.errors() {
+        output_event(connection, "stderr", &format!("{}\n", error))?;
+    }
+    let stmts = match Parser::new(&tokens).parse() {
+        Ok(stmts) if scanner.errors().is_empty() => stmts,
+        Ok(_) => return Ok(Some(65)),
+        Err(err) => {
+            output_event(connection, "stderr", &format!("{}\n", err))?;
+            return Ok(Some(65));
+        }
+    };
+
+    let quit = Rc::new(Cell::new(false));
+    let disconnected = Rc::new(Cell::new(false));
+    let frontend = Adapter {
+        connection: Rc::clone(connection),
+        path: launch.path.clone(),
+        quit: Rc::clone(&quit),
+        disconnected: Rc::clone(&disconnected),
+    };
+    let mut debugger = Debugger::new(Box::new(frontend));
+    if !launch.stop_on_entry {
+        debugger = debugger.run_to_breakpoint();
+    }
+    *debugger.breakpoints() = breakpoints;
+
+    let mut output = ProgramOutput {
+        connection: Rc::clone(connection),
+        line: Vec::new(),
+    };
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    interpreter.set_debugger(debugger);
+    let result = interpreter.interpret(&stmts);
+    output.flush()?;
+
+    if disconnected.get() {
+        return Ok(None);
+    }
+    match result {
+        Ok(()) => Ok(Some(0)),
+        Err(_) if quit.get() => Ok(Some(0)),
+        Err(err) => {
+            let line = err
+                .token
+                .map(|token| format!("[line {}]\n", token.line))
+                .unwrap_or_default();
+            output_event(connection, "stderr", &format!("{}\n{}", err.message, line))?;
+            Ok(Some(70))
+        }
+    }
+}
+
+/// The debugger frontend that reports pauses to the client and serves its requests
+/// until one resumes the program.
+struct Adapter {
+    connection: Shared,
+    path: String,
+    quit: Rc<Cell<bool>>,
+    /// Whether the client quit the program with a `disconnect`, ending the session.
+    disconnected: Rc<Cell<bool>>,
+}
+
+impl Frontend for Adapter {
+    fn paused(&mut self, pause: &mut Pause) -> Action {
+        // A broken connection leaves nobody to resume the program.
+        let action = self.serve(pause).unwrap_or(Action::Quit);
+        self.quit.set(action == Action::Quit);
+        action
+    }
+}
+
+impl Adapter {
+    fn serve(&mut self, pause: &mut Pause) -> io::Result<Action> {
+        let reason = match pause.reason {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        };
+        self.connection.borrow_mut().event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        // Variable references name a (frame, scope) pair, and are only valid while
+        // the program stays paused.
+        let mut references: Vec<(usize, usize)> = Vec::new();
+        loop {
+            let Some(request) = self.connection.borrow_mut().read()? else {
+                return Ok(Action::Quit);
+            };
+            let arguments = &request["arguments"];
+            let resume = match request["command"].as_str().unwrap_or_default() {
+                "continue" => Some(Action::Continue),
+                "next" => Some(Action::StepOver),
+                "stepIn" => Some(Action::StepIn),
+                "stepOut" => Some(Action::StepOut),
+                "disconnect" | "terminate" => Some(Action::Quit),
+                _ => None,
+            };
+            if let Some(action) = resume {
+                let body = match action {
+                    Action::Continue => json!({ "allThreadsContinued": true }),
+                    _ => json!({}),
+                };
+                self.connection.borrow_mut().respond(&request, Ok(body))?;
+                self.disconnected.set(request["command"] == "disconnect");
+                return Ok(action);
+            }
+
+            let result = match request["command"].as_str().unwrap_or_default() {
+                "threads" => Ok(threads()),
+                "setBreakpoints" => Ok(set_breakpoints(pause.breakpoints, arguments)),
+                "stackTrace" => Ok(self.stack_trace(pause)),
+                "scopes" => frame_argument(pause, arguments).map(|frame| {
+                    let count = pause.scopes(frame).len();
+                    let scopes: Vec<Value> = (0..count)
+                        .rev()
+                        .map(|scope| {
+                            references.push((frame, scope));
+                            let name = match scope {
+                                0 => "Globals".to_string(),
+                                _ if scope + 1 == count => "Locals".to_string(),
+                                _ => format!("Scope {}", scope),
+                            };
+                            json!({
+                                "name": name,
+                                "variablesReference": references.len(),
+                                "expensive": false,
+                            })
+                        })
+                        .collect();
+                    json!({ "scopes": scopes })
+                }),
+                "variables" => reference(&references, arguments).map(|(frame, scope)| {
+                    let variables: Vec<Value> = pause
+                        .scopes(frame)
+                        .swap_remove(scope)
+                        .into_iter()
+                        .map(|(name, value)| {
+                            json!({
+                                "name": name,
+                                "value": value.to_string(),
+                                "type": type_name(&value),
+                                "variablesReference": 0,
+                            })
+                        })
+                        .collect();
+                    json!({ "variables": variables })
+                }),
+                "setVariable" => reference(&references, arguments).and_then(|(frame, scope)| {
+                    let name = arguments["name"].as_str().unwrap_or_default();
+                    let text = arguments["value"].as_str().unwrap_or_default();
+                    let value = debugger::parse_value(text).ok_or_else(|| {
+                        format!("Expect a number, string, boolean or nil, got '{}'.", text)
+                    })?;
+                    if !pause.set_variable(frame, scope, name, value.clone()) {
+                        return Err(format!("Undefined variable '{}'.", name));
+                    }
+                    Ok(json!({ "value": value.to_string(), "type": type_name(&value) }))
+                }),
+                "evaluate" => frame_argument(pause, arguments).and_then(|frame| {
+                    let name = arguments["expression"].as_str().unwrap_or_default().trim();
+                    pause
+                        .scopes(frame)
+                        .into_iter()
+                        .rev()
+                        .find_map(|scope| scope.into_iter().find(|(variable, _)| variable == name))
+                        .map(|(_, value)| {
+                            json!({
+                                "result": value.to_string(),
+                                "type": type_name(&value),
+                                "variablesReference": 0,
+                            })
+                        })
+                        .ok_or_else(|| format!("Undefined variable '{}'.", name))
+                }),
+                command => Err(format!("Unsupported request '{}'.", command)),
+            };
+            self.connection.borrow_mut().respond(&request, result)?;
+        }
+    }
+
+    /// The frames innermost first. A frame's id is its index counting from the script.
+    fn stack_trace(&self, pause: &Pause) -> Value {
+        let frames: Vec<Value> = pause
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(id, frame)| {
+                json!({
+                    "id": id,
+                    "name": frame.name,
+                    "line": frame.line,
+                    "column": 1,
+                    "source": { "path": self.path },
+                })
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": pause.frames.len() })
+    }
+}
+
+/// The frame named by `frameId`, defaulting to the innermost one.
+fn frame_argument(pause: &Pause, arguments: &Value) -> Result<usize, String> {
+    match arguments["frameId"].as_u64() {
+        Some(frame) if (frame as usize) < pause.frames.len() => Ok(frame as usize),
+        Some(frame) => Err(format!("No frame {}.", frame)),
+        None => Ok(pause.frames.len() - 1),
+    }
+}
+
+fn reference(references: &[(usize, usize)], arguments: &Value) -> Result<(usize, usize), String> {
+    arguments["variablesReference"]
+        .as_u64()
+        .and_then(|reference| references.get((reference as usize).checked_sub(1)?))
+        .copied()
+        .ok_or_else(|| "Unknown variables reference.".to_string())
+}
+
+fn type_name(value: &Literal) -> &'static str {
+    match value {
+        Literal::Number(_) => "number",
+        Literal::String(_) => "string",
+        Literal::Boolean(_) => "boolean",
+        Literal::Callable(_) => "function",
+        Literal::Identifier(_) => "identifier",
+        Literal::None => "nil",
+    }
+}
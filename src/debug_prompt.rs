@@ -0,0 +1,329 @@
+use std::cell::Cell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use rlox::debugger::{self, Action, Frontend, Pause, Reason};
+
+pub const HELP: &str = "\
+Commands:
+  s, step             Run to the next statement, entering function calls.
+  n, next             Run to the next statement, stepping over function calls.
+  o, out              Run until the current function returns.
+  c, continue         Run until the next breakpoint.
+  b, break <line>     Pause before statements on <line>.
+  d, delete <line>    Remove the breakpoint on <line>.
+  breakpoints         List the breakpoints.
+  bt, backtrace       Show the function calls in progress, innermost first.
+  f, frame <n>        Inspect frame <n> of the backtrace.
+  v, vars             Show the variables of every scope in the frame, except
+                      native functions.
+  p, print <name>     Show the value of a variable.
+  set <name> <value>  Change a variable. Use <scope>.<name> to pick a scope
+                      other than the innermost one that has the variable.
+  l, list             Show the source around the current line.
+  q, quit             Stop the program.
+  h, help             Show this help.";
+
+/// A command prompt that drives the debugger, reading commands from `input` while
+/// the program is paused.
+pub struct DebugPrompt {
+    source: String,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// The frame being inspected, counted from the innermost one.
+    frame: usize,
+    /// Set when the user quits, so the caller can tell quitting from a runtime error.
+    quit: Rc<Cell<bool>>,
+}
+
+impl DebugPrompt {
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        DebugPrompt {
+            source: source.to_string(),
+            input,
+            output,
+            frame: 0,
+            quit: Rc::default(),
+        }
+    }
+
+    /// Shares a flag that is set when the user quits.
+    pub fn on_quit(mut self, quit: Rc<Cell<bool>>) -> Self {
+        self.quit = quit;
+        self
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source
+            .lines()
+            .nth(line.wrapping_sub(1))
+            .unwrap_or("")
+            .trim()
+    }
+
+    /// Runs one command. Returns the action to resume with, if the command resumes.
+    fn execute(&mut self, command: &str, pause: &mut Pause) -> Result<Option<Action>, String> {
+        let (name, argument) = command
+            .trim()
+            .split_once(char::is_whitespace)
+            .map_or((command.trim(), ""), |(name, argument)| {
+                (name, argument.trim())
+            });
+        // Frames are shown innermost first but stored outermost first.
+        let frame = pause.frames.len() - 1 - self.frame;
+
+        match name {
+            "s" | "step" => return Ok(Some(Action::StepIn)),
+            "n" | "next" => return Ok(Some(Action::StepOver)),
+            "o" | "out" => return Ok(Some(Action::StepOut)),
+            "c" | "continue" => return Ok(Some(Action::Continue)),
+            "q" | "quit" => return Ok(Some(Action::Quit)),
+            "b" | "break" | "d" | "delete" => {
+                let line = argument
+                    .parse::<usize>()
+                    .map_err(|_| format!("Expect a line number, got '{}'.", argument))?;
+                if name.starts_with('b') {
+                    pause.breakpoints.insert(line);
+                    self.print(&format!("Breakpoint set on line {}.", line));
+                } else if pause.breakpoints.remove(&line) {
+                    self.print(&format!("Breakpoint on line {} removed.", line));
+                } else {
+                    return Err(format!("No breakpoint on line {}.", line));
+                }
+            }
+            "breakpoints" => {
+                let lines: Vec<String> = pause.breakpoints.iter().map(usize::to_string).collect();
+                match lines.is_empty() {
+                    true => self.print("No breakpoints."),
+                    false => self.print(&format!("Breakpoints on lines {}.", lines.join(", "))),
+                }
+            }
+            "bt" | "backtrace" => {
+                for (i, frame) in pause.frames.iter().rev().enumerate() {
+                    let marker = if i == self.frame { '>' } else { ' ' };
+                    self.print(&format!(
+                        "{}#{} {} at line {}",
+                        marker, i, frame.name, frame.line
+                    ));
+                }
+            }
+            "f" | "frame" => {
+                let index = argument
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|index| *index < pause.frames.len())
+                    .ok_or_else(|| format!("No frame '{}'.", argument))?;
+                self.frame = index;
+                let frame = &pause.frames[pause.frames.len() - 1 - index];
+                self.print(&format!("#{} {} at line {}", index, frame.name, frame.line));
+            }
+            "v" | "vars" => {
+                for (i, scope) in pause.scopes(frame).iter().enumerate() {
+                    let title = if i == 0 { " (globals)" } else { "" };
+                    self.print(&format!("scope {}{}:", i, title));
+                    for (name, value) in scope.iter().filter(|(_, value)| !value.is_native()) {
+                        self.print(&format!("  {} = {}", name, value));
+                    }
+                }
+            }
+            "p" | "print" => {
+                let (_, value) = find_variable(&pause.scopes(frame), argument)
+                    .ok_or_else(|| format!("Undefined variable '{}'.", argument))?;
+                self.print(&format!("{} = {}", argument, value));
+            }
+            "set" => {
+                let (target, value_text) = argument
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "Usage: set <name> <value>".to_string())?;
+                let value = debugger::parse_value(value_text.trim()).ok_or_else(|| {
+                    format!(
+                        "Expect a number, string, boolean or nil, got '{}'.",
+                        value_text.trim()
+                    )
+                })?;
+
+                let scopes = pause.scopes(frame);
+                let (scope, variable) = match target.split_once('.') {
+                    Some((scope, variable)) => {
+                        let scope = scope
+                            .parse::<usize>()
+                            .map_err(|_| format!("Expect a scope number, got '{}'.", scope))?;
+                        (scope, variable)
+                    }
+                    None => {
+                        let (scope, _) = find_variable(&scopes, target)
+                            .ok_or_else(|| format!("Undefined variable '{}'.", target))?;
+                        (scope, target)
+                    }
+                };
+                if !pause.set_variable(frame, scope, variable, value.clone()) {
+                    return Err(format!("No variable '{}' in scope {}.", variable, scope));
+                }
+                self.print(&format!("{} = {}", variable, value));
+            }
+            "l" | "list" => {
+                let current = pause.frames[frame].line;
+                let first = current.saturating_sub(3).max(1);
+                let lines: Vec<String> = self
+                    .source
+                    .lines()
+                    .enumerate()
+                    .skip(first - 1)
+                    .take(7)
+                    .map(|(i, text)| {
+                        let marker = if i + 1 == current { "->" } else { "  " };
+                        format!("{} {:>4} {}", marker, i + 1, text)
+                    })
+                    .collect();
+                for line in lines {
+                    self.print(&line);
+                }
+            }
+            "h" | "help" => self.print(HELP),
+            "" => {}
+            _ => {
+                return Err(format!(
+                    "Unknown command '{}'. Type 'help' for a list.",
+                    name
+                ))
+            }
+        }
+        Ok(None)
+    }
+
+    fn print(&mut self, text: &str) {
+        // The prompt has nowhere else to report a failing output stream.
+        let _ = writeln!(self.output, "{}", text);
+    }
+}
+
+/// Finds the innermost scope defining `name`, with its value.
+fn find_variable(
+    scopes: &[Vec<(String, rlox::expr::Literal)>],
+    name: &str,
+) -> Option<(usize, rlox::expr::Literal)> {
+    scopes.iter().enumerate().rev().find_map(|(i, scope)| {
+        scope
+            .iter()
+            .find(|(variable, _)| variable == name)
+            .map(|(_, value)| (i, value.clone()))
+    })
+}
+
+impl Frontend for DebugPrompt {
+    fn paused(&mut self, pause: &mut Pause) -> Action {
+        self.frame = 0;
+        let reason = match pause.reason {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        };
+        let line = pause.line();
+        let text = format!(
+            "Paused at line {} ({}): {}",
+            line,
+            reason,
+            self.source_line(line)
+        );
+        self.print(&text);
+
+        loop {
+            let _ = write!(self.output, "(debug) ");
+            let _ = self.output.flush();
+            let mut command = String::new();
+            let action = match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => Ok(Some(Action::Quit)),
+                Ok(_) => self.execute(&command, pause),
+            };
+            match action {
+                Ok(Some(action)) => {
+                    self.quit.set(action == Action::Quit);
+                    return action;
+                }
+                Ok(None) => {}
+                Err(message) => self.print(&message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlox::{debugger::Debugger, environment::Environment, interpreter::Interpreter};
+    use std::{cell::RefCell, io, rc::Rc};
+
+    /// An output stream that can still be read after being moved into the prompt.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(source: &str, commands: &str) -> (String, String) {
+        let transcript = Shared::default();
+        let prompt = DebugPrompt::new(
+            source,
+            Box::new(io::Cursor::new(commands.to_string())),
+            Box::new(transcript.clone()),
+        );
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+        interpreter.set_debugger(Debugger::new(Box::new(prompt)));
+        let mut rlox = rlox::RLox::new(interpreter);
+        rlox.run(source);
+
+        let transcript = String::from_utf8(transcript.0.borrow().clone()).unwrap();
+        (transcript, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_session() {
+        let source = "var x = 1;\nfun f(a) {\n  var b = a * 2;\n  return b;\n}\nprint f(x);\n";
+        let commands = "break 4\nbreakpoints\ncontinue\nbt\nvars\nset b 10\nframe 1\nset x -1\np x\ncontinue\n";
+        let (transcript, output) = session(source, commands);
+
+        assert_eq!(
+            transcript,
+            "Paused at line 1 (entry): var x = 1;\n\
+             (debug) Breakpoint set on line 4.\n\
+             (debug) Breakpoints on lines 4.\n\
+             (debug) Paused at line 4 (breakpoint): return b;\n\
+             (debug) >#0 f at line 4\n #1 script at line 6\n\
+             (debug) scope 0 (globals):\n  f = <fn f>\n  x = 1\n\
+             scope 1:\n  a = 1\n  b = 2\n\
+             (debug) b = 10\n\
+             (debug) #1 script at line 6\n\
+             (debug) x = -1\n\
+             (debug) x = -1\n\
+             (debug) "
+        );
+        assert_eq!(output, "10\n");
+    }
+
+    #[test]
+    fn test_errors_and_quit() {
+        let (transcript, output) = session(
+            "print 1;\nprint 2;",
+            "bogus\nset y 1\np y\nset\nnext\nquit\n",
+        );
+        assert_eq!(
+            transcript,
+            "Paused at line 1 (entry): print 1;\n\
+             (debug) Unknown command 'bogus'. Type 'help' for a list.\n\
+             (debug) Undefined variable 'y'.\n\
+             (debug) Undefined variable 'y'.\n\
+             (debug) Usage: set <name> <value>\n\
+             (debug) Paused at line 2 (step): print 2;\n\
+             (debug) "
+        );
+        assert_eq!(output, "1\n");
+    }
+}
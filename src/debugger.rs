@@ -0,0 +1,389 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    environment::Environment,
+    error::RuntimeError,
+    expr::{Expr, Literal},
+    parser::Parser,
+    runtime_err,
+    scanner::Scanner,
+    stmt::Stmt,
+};
+
+/// How to go on after a pause.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next statement, including inside called functions.
+    StepIn,
+    /// Pause at the next statement in the current function or its callers.
+    StepOver,
+    /// Pause at the next statement after the current function returns.
+    StepOut,
+    /// Stop the program with a runtime error.
+    Quit,
+}
+
+/// Why execution paused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// A function call in progress. The first frame is the script itself.
+#[derive(Debug)]
+pub struct Frame {
+    pub name: String,
+    /// The line of the statement the frame is executing.
+    pub line: usize,
+    /// The frame's variables while it waits for a call to return. The innermost
+    /// frame's variables are the interpreter's live environment instead.
+    environment: Option<Environment>,
+}
+
+/// The state of a paused program, handed to the [`Frontend`].
+pub struct Pause<'a> {
+    pub reason: Reason,
+    pub frames: &'a [Frame],
+    pub breakpoints: &'a mut BTreeSet<usize>,
+    environment: &'a mut Environment,
+}
+
+impl Pause<'_> {
+    /// The line of the statement about to run.
+    pub fn line(&self) -> usize {
+        self.frames.last().map_or(0, |frame| frame.line)
+    }
+
+    fn environment(&self, frame: usize) -> Option<&Environment> {
+        if frame + 1 == self.frames.len() {
+            Some(self.environment)
+        } else {
+            self.frames.get(frame)?.environment.as_ref()
+        }
+    }
+
+    /// The variables of every scope visible in a frame, outermost (global) first.
+    pub fn scopes(&self, frame: usize) -> Vec<Vec<(String, Literal)>> {
+        self.environment(frame)
+            .map(Environment::scopes)
+            .unwrap_or_default()
+    }
+
+    /// Changes an existing variable in one scope of a frame. Returns `false` if there
+    /// is no such frame, scope or variable.
+    pub fn set_variable(&mut self, frame: usize, scope: usize, name: &str, value: Literal) -> bool {
+        if frame + 1 == self.frames.len() {
+            return self.environment.set_in_scope(scope, name, value);
+        }
+        // Suspended frames share their scopes with the captured environment.
+        match self
+            .frames
+            .get(frame)
+            .and_then(|frame| frame.environment.as_ref())
+        {
+            Some(environment) => environment.capture().set_in_scope(scope, name, value),
+            None => false,
+        }
+    }
+}
+
+/// The user interface of the debugger, such as a command prompt or a protocol server.
+pub trait Frontend {
+    /// Called whenever execution pauses. Returns how to resume.
+    fn paused(&mut self, pause: &mut Pause) -> Action;
+}
+
+/// Pauses execution at breakpoints and while stepping, and hands control to a
+/// [`Frontend`] to inspect and change the program's state.
+pub struct Debugger {
+    frontend: Box<dyn Frontend>,
+    breakpoints: BTreeSet<usize>,
+    action: Action,
+    /// The number of frames when the last step was requested.
+    step_depth: usize,
+    frames: Vec<Frame>,
+    started: bool,
+}
+
+impl Debugger {
+    /// Creates a debugger that pauses before the first statement.
+    pub fn new(frontend: Box<dyn Frontend>) -> Self {
+        Debugger {
+            frontend,
+            breakpoints: BTreeSet::new(),
+            action: Action::StepIn,
+            step_depth: 1,
+            frames: vec![Frame {
+                name: "script".to_string(),
+                line: 0,
+                environment: None,
+            }],
+            started: false,
+        }
+    }
+
+    /// Starts running without pausing until a breakpoint is hit.
+    pub fn run_to_breakpoint(mut self) -> Self {
+        self.action = Action::Continue;
+        self
+    }
+
+    pub fn breakpoints(&mut self) -> &mut BTreeSet<usize> {
+        &mut self.breakpoints
+    }
+
+    /// Called before each statement runs, with the environment it runs in. Blocks
+    /// are skipped, since their first statement is on the same line.
+    pub(crate) fn before_stmt(
+        &mut self,
+        stmt: &Stmt,
+        environment: &mut Environment,
+    ) -> Result<(), RuntimeError> {
+        let Some(line) = stmt.line() else {
+            return Ok(());
+        };
+        if matches!(stmt, Stmt::Block(_)) {
+            return Ok(());
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+
+        let depth = self.frames.len();
+        let reason = match self.action {
+            Action::StepIn if !self.started => Some(Reason::Entry),
+            Action::StepIn => Some(Reason::Step),
+            Action::StepOver if depth <= self.step_depth => Some(Reason::Step),
+            Action::StepOut if depth < self.step_depth => Some(Reason::Step),
+            _ if self.breakpoints.contains(&line) => Some(Reason::Breakpoint),
+            _ => None,
+        };
+        self.started = true;
+        let Some(reason) = reason else {
+            return Ok(());
+        };
+
+        let mut pause = Pause {
+            reason,
+            frames: &self.frames,
+            breakpoints: &mut self.breakpoints,
+            environment,
+        };
+        self.action = self.frontend.paused(&mut pause);
+        self.step_depth = depth;
+
+        match self.action {
+            Action::Quit => Err(runtime_err!("Execution stopped by the debugger.", None)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Called when a function is about to run, with the caller's environment.
+    pub(crate) fn enter_function(&mut self, name: &str, caller: &Environment) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.environment = Some(caller.capture());
+        }
+        self.frames.push(Frame {
+            name: name.to_string(),
+            line: 0,
+            environment: None,
+        });
+    }
+
+    /// Called when a function has returned.
+    pub(crate) fn exit_function(&mut self) {
+        self.frames.pop();
+        if let Some(frame) = self.frames.last_mut() {
+            frame.environment = None;
+        }
+    }
+}
+
+/// Parses a value typed by the user: a number, string, `true`, `false` or `nil`,
+/// optionally negated.
+pub fn parse_value(text: &str) -> Option<Literal> {
+    let mut scanner = Scanner::new(text);
+    let tokens = scanner.scan_tokens().clone();
+    if !scanner.errors().is_empty() {
+        return None;
+    }
+    match Parser::new(&tokens).parse_expression()?.ok()? {
+        Expr::Literal(literal) => Some(literal),
+        Expr::Unary { operator, right } if operator.lexeme == "-" => match *right {
+            Expr::Literal(Literal::Number(n)) => Some(Literal::Number(-n)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::Interpreter, parser::Parser};
+    use std::{cell::RefCell, io::Write, rc::Rc};
+
+    /// A frontend that records each pause and replays a fixed list of actions.
+    struct Script {
+        actions: Vec<Action>,
+        pauses: Rc<RefCell<Vec<(Reason, usize, usize)>>>,
+        on_pause: fn(&mut Pause),
+    }
+
+    impl Frontend for Script {
+        fn paused(&mut self, pause: &mut Pause) -> Action {
+            self.pauses
+                .borrow_mut()
+                .push((pause.reason, pause.line(), pause.frames.len()));
+            (self.on_pause)(pause);
+            if self.actions.is_empty() {
+                Action::Continue
+            } else {
+                self.actions.remove(0)
+            }
+        }
+    }
+
+    const SOURCE: &str = "fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 1);";
+
+    fn debug(
+        actions: Vec<Action>,
+        breakpoints: &[usize],
+        on_pause: fn(&mut Pause),
+    ) -> (Vec<(Reason, usize, usize)>, String) {
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let frontend = Script {
+            actions,
+            pauses: Rc::clone(&pauses),
+            on_pause,
+        };
+        let mut debugger = Debugger::new(Box::new(frontend));
+        debugger.breakpoints().extend(breakpoints);
+
+        let mut scanner = Scanner::new(SOURCE);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+        interpreter.set_debugger(debugger);
+        let result = interpreter.interpret(&stmts);
+        if let Err(err) = result {
+            writeln!(output, "error: {}", err.message).unwrap();
+        }
+
+        let pauses = pauses.borrow().clone();
+        (pauses, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_stepping() {
+        let test_cases = vec![
+            (
+                vec![
+                    Action::StepIn,
+                    Action::StepIn,
+                    Action::StepIn,
+                    Action::Continue,
+                ],
+                vec![
+                    (Reason::Entry, 1, 1),
+                    (Reason::Step, 5, 1),
+                    (Reason::Step, 2, 2),
+                    (Reason::Step, 3, 2),
+                ],
+            ),
+            (
+                vec![Action::StepIn, Action::StepOver, Action::StepOver],
+                vec![
+                    (Reason::Entry, 1, 1),
+                    (Reason::Step, 5, 1),
+                    (Reason::Step, 6, 1),
+                    (Reason::Step, 7, 1),
+                ],
+            ),
+            (
+                vec![
+                    Action::StepIn,
+                    Action::StepIn,
+                    Action::StepOut,
+                    Action::Continue,
+                ],
+                vec![
+                    (Reason::Entry, 1, 1),
+                    (Reason::Step, 5, 1),
+                    (Reason::Step, 2, 2),
+                    (Reason::Step, 6, 1),
+                ],
+            ),
+        ];
+
+        for (actions, expected) in test_cases {
+            let (pauses, output) = debug(actions.clone(), &[], |_| {});
+            assert_eq!(pauses, expected, "Failed on actions: {:?}", actions);
+            assert_eq!(output, "3\n4\n");
+        }
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let (pauses, _) = debug(vec![Action::Continue], &[3, 6], |_| {});
+        assert_eq!(
+            pauses,
+            vec![
+                (Reason::Entry, 1, 1),
+                (Reason::Breakpoint, 3, 2),
+                (Reason::Breakpoint, 6, 1),
+                (Reason::Breakpoint, 3, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inspect_and_modify_variables() {
+        let (_, output) = debug(vec![Action::Continue], &[3], |pause| {
+            if pause.reason != Reason::Breakpoint {
+                return;
+            }
+            let scopes = pause.scopes(1);
+            let locals = scopes.last().unwrap();
+            let names: Vec<&str> = locals.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["a", "b", "sum"]);
+            assert!(pause.scopes(0)[0].iter().any(|(name, _)| name == "add"));
+
+            let depth = scopes.len() - 1;
+            assert!(pause.set_variable(1, depth, "sum", Literal::Number(10.0)));
+            assert!(!pause.set_variable(1, depth, "missing", Literal::None));
+        });
+        assert_eq!(output, "10\n10\n");
+    }
+
+    #[test]
+    fn test_quit() {
+        let (pauses, output) = debug(vec![Action::Quit], &[], |_| {});
+        assert_eq!(pauses.len(), 1);
+        assert_eq!(output, "error: Execution stopped by the debugger.\n");
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("-1.5"), Some(Literal::Number(-1.5)));
+        assert_eq!(
+            parse_value("\"hi\""),
+            Some(Literal::String("hi".to_string()))
+        );
+        assert_eq!(parse_value("nil"), Some(Literal::None));
+        assert_eq!(parse_value("true"), Some(true.into()));
+        assert_eq!(parse_value("x"), None);
+        assert_eq!(parse_value("1 + 2"), None);
+    }
+}
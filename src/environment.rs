@@ -1,4 +1,6 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::error::RuntimeError;
 use crate::expr::Literal;
@@ -6,12 +8,15 @@ use crate::runtime_err;
 use crate::token::Token;
 use anyhow::Result;
 
+/// A single scope, shared between the environment and any closures that capture it.
+type Scope = Rc<RefCell<HashMap<String, Literal>>>;
+
 /// Represents the environment in which variables and their values are stored.
 /// Manages a stack of scopes, each of which is a mapping of variable names to their values.
 #[derive(Debug)]
 pub struct Environment {
     /// A stack of scopes, where each scope is a `HashMap` mapping variable names to their values.
-    scopes: Vec<HashMap<String, Literal>>,
+    scopes: Vec<Scope>,
 }
 
 impl Default for Environment {
@@ -24,14 +29,73 @@ impl Environment {
     /// Creates a new `Environment` with a single, empty scope.
     pub fn new() -> Self {
         Environment {
-            scopes: vec![HashMap::new()],
+            scopes: vec![Scope::default()],
+        }
+    }
+
+    /// Creates an environment that shares every scope of this one.
+    /// Used by closures, which must see later changes to the variables they capture.
+    pub fn capture(&self) -> Environment {
+        Environment {
+            scopes: self.scopes.clone(),
+        }
+    }
+
+    /// Returns the variables defined in the outermost (global) scope, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Literal)> {
+        let mut globals: Vec<(String, Literal)> = self.scopes[0]
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        globals
+    }
+
+    /// Returns the variables of every scope, outermost (global) first, each sorted by name.
+    pub fn scopes(&self) -> Vec<Vec<(String, Literal)>> {
+        self.scopes
+            .iter()
+            .map(|scope| {
+                let mut variables: Vec<(String, Literal)> = scope
+                    .borrow()
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                variables.sort_by(|(a, _), (b, _)| a.cmp(b));
+                variables
+            })
+            .collect()
+    }
+
+    /// Changes a variable that already exists in the scope at `index`, counting from the
+    /// global scope. Returns `false` if the scope has no such variable.
+    pub fn set_in_scope(&mut self, index: usize, name: &str, value: Literal) -> bool {
+        let Some(scope) = self.scopes.get(index) else {
+            return false;
+        };
+        let mut scope = scope.borrow_mut();
+        match scope.get_mut(name) {
+            Some(variable) => {
+                *variable = value;
+                true
+            }
+            None => false,
         }
     }
 
     /// Adds a new scope to the environment.
     /// Scopes are used to manage variable lifetimes during block execution.
     pub fn add_scope(&mut self) {
-        self.scopes.push(HashMap::new());
+        self.scopes.push(Scope::default());
+    }
+
+    /// Returns whether a closure shares the innermost scope, keeping it alive after the
+    /// scope is removed from this environment.
+    pub fn is_captured(&self) -> bool {
+        self.scopes
+            .last()
+            .is_some_and(|scope| Rc::strong_count(scope) > 1)
     }
 
     /// Removes the most recently added scope from the environment.
@@ -41,7 +105,10 @@ impl Environment {
     ///
     /// This function will panic if there are no scopes to remove.
     pub fn pop_scope(&mut self) -> HashMap<String, Literal> {
-        self.scopes.pop().unwrap()
+        let scope = self.scopes.pop().unwrap();
+        Rc::try_unwrap(scope)
+            .map(RefCell::into_inner)
+            .unwrap_or_else(|scope| scope.borrow().clone())
     }
 
     /// Defines a new variable in the current (innermost) scope.
@@ -50,8 +117,12 @@ impl Environment {
     ///
     /// * `name` - The name of the variable to define.
     /// * `value` - The value of the variable.
-    pub fn define(&mut self, name: String, value: Literal) {
-        self.scopes.last_mut().unwrap().insert(name, value);
+    ///
+    /// # Returns
+    ///
+    /// The value of the variable this one replaces, if the scope already had one.
+    pub fn define(&mut self, name: String, value: Literal) -> Option<Literal> {
+        self.scopes.last().unwrap().borrow_mut().insert(name, value)
     }
 
     /// Retrieves the value of a variable.
@@ -63,11 +134,11 @@ impl Environment {
     ///
     /// # Returns
     ///
-    /// A reference to the variable's value if it is found, otherwise a `RuntimeError`.
-    pub fn get(&self, name: &Token) -> Result<&Literal, RuntimeError> {
+    /// A copy of the variable's value if it is found, otherwise a `RuntimeError`.
+    pub fn get(&self, name: &Token) -> Result<Literal, RuntimeError> {
         for scope in self.scopes.iter().rev() {
-            if let Some(value) = scope.get(&name.lexeme) {
-                return Ok(value);
+            if let Some(value) = scope.borrow().get(&name.lexeme) {
+                return Ok(value.clone());
             }
         }
         Err(runtime_err!(
@@ -86,12 +157,12 @@ impl Environment {
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or a `RuntimeError` if the variable is not defined.
-    pub fn assign(&mut self, name: &Token, value: &Literal) -> Result<(), RuntimeError> {
-        for scope in self.scopes.iter_mut().rev() {
-            if scope.contains_key(&name.lexeme) {
-                scope.insert(name.lexeme.clone(), value.clone());
-                return Ok(());
+    /// The previous value of the variable, or a `RuntimeError` if the variable is not
+    /// defined.
+    pub fn assign(&mut self, name: &Token, value: &Literal) -> Result<Literal, RuntimeError> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(variable) = scope.borrow_mut().get_mut(&name.lexeme) {
+                return Ok(std::mem::replace(variable, value.clone()));
             }
         }
         Err(runtime_err!(
@@ -1,9 +1,33 @@
+use crate::expr::Literal;
 use crate::token::Token;
 
+/// What kind of failure stopped the program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An error in the program itself, such as a type error or an undefined variable.
+    Runtime,
+    /// The program executed more statements than its limit allows.
+    StatementLimit,
+    /// The program ran past its deadline.
+    Timeout,
+    /// Function calls nested deeper than the limit, or the stack, allows.
+    CallDepth,
+    /// The program allocated more memory than its limit allows.
+    OutOfMemory,
+    /// The host cancelled the program through its cancellation token.
+    Cancelled,
+    /// The program called a native function whose capability is disabled.
+    PermissionDenied,
+    /// A call to `assert` or `assert_eq` failed.
+    AssertionFailed,
+}
+
 /// Represents a runtime error that occurs during the execution of the interpreter.
 /// Contains an error message and the token associated with the error.
 #[derive(Debug)]
 pub struct RuntimeError {
+    /// The kind of failure, which tells errors in the program from exceeded limits.
+    pub kind: ErrorKind,
     /// The error message describing what went wrong.
     pub message: String,
     /// The token associated with the error, providing context such as the location in the source code.
@@ -18,7 +42,16 @@ impl RuntimeError {
     /// * `message` - A string describing the error.
     /// * `token` - The token associated with the error.
     pub fn new(message: String, token: Option<Token>) -> Self {
-        Self { message, token }
+        Self::with_kind(ErrorKind::Runtime, message, token)
+    }
+
+    /// Creates a new `RuntimeError` of the given kind.
+    pub fn with_kind(kind: ErrorKind, message: String, token: Option<Token>) -> Self {
+        Self {
+            kind,
+            message,
+            token,
+        }
     }
 }
 
@@ -26,6 +59,7 @@ impl RuntimeError {
 ///
 /// # Arguments
 ///
+/// * `$kind` - The `ErrorKind`, `ErrorKind::Runtime` if omitted.
 /// * `$message` - A string literal describing the error.
 /// * `$token` - The token associated with the error.
 #[macro_export]
@@ -33,4 +67,23 @@ macro_rules! runtime_err {
     ($message:expr, $token:expr) => {
         RuntimeError::new($message.to_string(), $token)
     };
+    ($kind:expr, $message:expr, $token:expr) => {
+        RuntimeError::with_kind($kind, $message.to_string(), $token)
+    };
+}
+
+/// The ways statement execution can stop before reaching the end of a block:
+/// a runtime error, or a `return` unwinding to the enclosing function call.
+#[derive(Debug)]
+pub(crate) enum Unwind {
+    /// A runtime error that aborts execution.
+    Error(RuntimeError),
+    /// A `return` statement carrying the returned value.
+    Return(Literal),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
 }
@@ -1,5 +1,8 @@
+use crate::callable::Callable;
+use crate::stmt::{self, Stmt};
 use crate::token::Token;
 use std::fmt::Display;
+use std::rc::Rc;
 
 /// Macro to convert a value into a Literal using `.into()`.
 /// Usage: `lit!(value)`
@@ -28,6 +31,10 @@ macro_rules! expr_lit {
 /// - `Literal`: A literal value (e.g., number, string, boolean).
 /// - `Unary`: A unary operation (e.g., negation) with an operator and a right operand.
 /// - `Variable`: A variable expression that evaluates to the variable's value.
+/// - `Logical`: A short-circuiting logical operation (`and`, `or`, `??`).
+/// - `Conditional`: A ternary conditional expression (`cond ? a : b`).
+/// - `Call`: A call of a function value with a list of arguments.
+/// - `Lambda`: An anonymous function expression.
 #[derive(Debug, Clone)]
 pub enum Expr {
     /// An assignment expression.
@@ -76,13 +83,51 @@ pub enum Expr {
     ///
     /// # Fields
     /// - `left`: The left-hand side expression.
-    /// - `operator`: The operator token (e.g., 'and', 'or').
+    /// - `operator`: The operator token (e.g., 'and', 'or', '??').
     /// - `right`: The right-hand side expression.
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+
+    /// A ternary conditional expression.
+    /// Evaluates only the branch selected by the condition.
+    ///
+    /// # Fields
+    /// - `condition`: The condition expression.
+    /// - `then_branch`: The expression evaluated when the condition is truthy.
+    /// - `else_branch`: The expression evaluated when the condition is falsey.
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
+
+    /// A call expression.
+    ///
+    /// # Fields
+    /// - `callee`: The expression evaluating to the function being called.
+    /// - `paren`: The closing parenthesis token, used to report errors.
+    /// - `arguments`: The argument expressions.
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+
+    /// An anonymous function expression, written `fun (a) { ... }` or `(a) => expr`.
+    /// The arrow form's body is a single `return` of its expression.
+    ///
+    /// # Fields
+    /// - `keyword`: The `fun` or `=>` token, used to report errors.
+    /// - `params`: The parameter names.
+    /// - `body`: The statements making up the function body.
+    Lambda {
+        keyword: Token,
+        params: Vec<Token>,
+        body: Rc<Vec<Stmt>>,
+    },
 }
 
 impl Display for Expr {
@@ -117,6 +162,27 @@ impl Display for Expr {
             } => {
                 write!(f, "({} {} {})", operator.lexeme, left, right)
             }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write!(f, "(?: {} {} {})", condition, then_branch, else_branch)
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Lambda { params, body, .. } => {
+                write!(f, "(fun ")?;
+                stmt::write_function(f, params, body)?;
+                write!(f, ")")
+            }
         }
     }
 }
@@ -174,6 +240,35 @@ impl From<bool> for Expr {
 }
 
 impl Expr {
+    /// Returns the expression's own token, such as its operator or name, or that of the
+    /// first child that has one. Literals carry no token, so an expression made only of
+    /// literals has none.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Expr::Assign { name, .. } | Expr::Variable { name } => Some(name),
+            Expr::Binary { operator, .. }
+            | Expr::Unary { operator, .. }
+            | Expr::Logical { operator, .. } => Some(operator),
+            Expr::Call { paren, .. } => Some(paren),
+            Expr::Lambda { keyword, .. } => Some(keyword),
+            Expr::Grouping { expression } => expression.token(),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => condition
+                .token()
+                .or_else(|| then_branch.token())
+                .or_else(|| else_branch.token()),
+            Expr::Literal(_) => None,
+        }
+    }
+
+    /// Returns the source line of the expression's [token](Expr::token).
+    pub fn line(&self) -> Option<usize> {
+        self.token().map(|token| token.line)
+    }
+
     pub fn literal<T>(value: T) -> Self
     where
         T: Into<Literal>,
@@ -224,6 +319,7 @@ pub enum Literal {
     String(String),
     Boolean(LiteralBool),
     Identifier(String),
+    Callable(Callable),
     None,
 }
 
@@ -234,6 +330,7 @@ impl Display for Literal {
             Literal::String(s) => write!(f, "\"{}\"", s),
             Literal::Identifier(s) => write!(f, "{}", s),
             Literal::Boolean(b) => write!(f, "{}", b),
+            Literal::Callable(c) => write!(f, "{}", c),
             Literal::None => write!(f, "nil"),
         }
     }
@@ -246,6 +343,11 @@ impl Literal {
             _ => self.to_string(),
         }
     }
+
+    /// Whether the value is a built-in function, which listings of variables leave out.
+    pub fn is_native(&self) -> bool {
+        matches!(self, Literal::Callable(Callable::Native(_)))
+    }
 }
 
 #[cfg(test)]
@@ -285,6 +387,8 @@ mod tests {
                         lexeme: "x".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     value: Box::new(Expr::Literal(123.0.into())),
                 },
@@ -297,6 +401,8 @@ mod tests {
                         lexeme: "y".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                 },
                 expected: "y",
@@ -329,6 +435,14 @@ mod tests {
                 },
                 expected: "(or (group (and true false)) (group true))",
             },
+            TestCase {
+                input: Expr::Conditional {
+                    condition: Box::new(true.into()),
+                    then_branch: Box::new(1.0.into()),
+                    else_branch: Box::new(2.0.into()),
+                },
+                expected: "(?: true 1 2)",
+            },
         ];
 
         for case in test_cases {
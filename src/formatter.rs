@@ -0,0 +1,552 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    expr::{Expr, Literal, LiteralBool},
+    parser::Parser,
+    scanner::{Comment, Scanner},
+    stmt::Stmt,
+    token::{self, Token, TokenKind},
+};
+
+const INDENT: &str = "  ";
+
+/// Formats Lox source code in the canonical style: two-space indentation, one
+/// statement per line, braces on the line that opens the block, and single spaces
+/// around binary operators.
+///
+/// Comments are kept, either on their own line or after the code they follow, and
+/// single blank lines between statements are preserved. Formatting never changes the
+/// parsed program, so the source must scan and parse without errors.
+pub fn format_source(source: &str) -> Result<String> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().clone();
+    if let Some(error) = scanner.errors().first() {
+        return Err(anyhow!("{}", error));
+    }
+    let stmts = Parser::new(&tokens).parse()?;
+
+    let mut formatter = Formatter::new(source, &tokens, scanner.comments());
+    formatter.stmts(&stmts);
+    formatter.remaining_comments(0);
+    Ok(formatter.out)
+}
+
+/// Formats a single expression in the canonical style.
+pub fn format_expr(expr: &Expr) -> String {
+    let mut formatter = Formatter::new("", &[], &[]);
+    formatter.expr(expr);
+    formatter.out
+}
+
+/// A comment waiting to be written, with the number of braces enclosing it.
+struct PendingComment {
+    comment: Comment,
+    depth: usize,
+}
+
+struct Formatter {
+    out: String,
+    comments: VecDeque<PendingComment>,
+    blank_lines: Vec<bool>,
+    indent: usize,
+    depth: usize,
+    /// Whether nothing has been written yet in the current block, so that it does not
+    /// start with a blank line.
+    block_start: bool,
+    /// The source spelling of the number literals by value, in source order, so that
+    /// numbers such as `0xFF` or `1e400` are written as they were.
+    numbers: HashMap<u64, VecDeque<String>>,
+}
+
+impl Formatter {
+    fn new(source: &str, tokens: &[Token], comments: &[Comment]) -> Self {
+        let depth_before = |index: usize| {
+            tokens[..index]
+                .iter()
+                .fold(0usize, |depth, token| match token.kind {
+                    TokenKind::LeftBrace => depth + 1,
+                    TokenKind::RightBrace => depth.saturating_sub(1),
+                    _ => depth,
+                })
+        };
+
+        let mut numbers: HashMap<u64, VecDeque<String>> = HashMap::new();
+        for token in tokens {
+            if let token::Literal::Number(value) = token.literal {
+                numbers
+                    .entry(value.to_bits())
+                    .or_default()
+                    .push_back(token.lexeme.clone());
+            }
+        }
+
+        Formatter {
+            out: String::new(),
+            comments: comments
+                .iter()
+                .map(|comment| PendingComment {
+                    comment: comment.clone(),
+                    depth: depth_before(comment.next_token),
+                })
+                .collect(),
+            blank_lines: source.lines().map(|line| line.trim().is_empty()).collect(),
+            indent: 0,
+            depth: 0,
+            block_start: true,
+            numbers,
+        }
+    }
+
+    /// Starts a new output line for code that began on `line` in the source, keeping
+    /// a blank line in front of it if the source had one.
+    fn start_line(&mut self, line: Option<usize>) {
+        let after_blank = line.is_some_and(|line| {
+            line >= 2 && self.blank_lines.get(line - 2).copied().unwrap_or(false)
+        });
+        if after_blank && !self.block_start {
+            self.out.push('\n');
+        }
+        self.block_start = false;
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    fn own_line_comment(&mut self, comment: Comment) {
+        self.start_line(Some(comment.line));
+        self.out.push_str(&comment.text);
+        self.out.push('\n');
+    }
+
+    /// Writes the comments that appear before `line` on lines of their own, leaving
+    /// those inside a block that starts before `line` to the block.
+    fn leading_comments(&mut self, line: usize) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|pending| pending.comment.line < line && pending.depth <= self.depth)
+        {
+            let pending = self.comments.pop_front().unwrap();
+            self.own_line_comment(pending.comment);
+        }
+    }
+
+    /// Appends the comment that ends the line of the code just written, if any. Code
+    /// starting on `next_line` no longer belongs to the current line.
+    fn trailing_comment(&mut self, next_line: Option<usize>) {
+        let is_trailing = self.comments.front().is_some_and(|pending| {
+            pending.comment.trailing
+                && pending.depth == self.depth
+                && next_line.is_none_or(|line| pending.comment.line < line)
+        });
+        if is_trailing {
+            let pending = self.comments.pop_front().unwrap();
+            self.out.push(' ');
+            self.out.push_str(&pending.comment.text);
+        }
+    }
+
+    /// Writes the comments nested at least `depth` braces deep on lines of their own.
+    fn remaining_comments(&mut self, depth: usize) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|pending| pending.depth >= depth)
+        {
+            let pending = self.comments.pop_front().unwrap();
+            self.own_line_comment(pending.comment);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for (i, stmt) in stmts.iter().enumerate() {
+            let next_line = stmts[i + 1..].iter().find_map(Stmt::line);
+            if let Some(line) = stmt.line() {
+                self.leading_comments(line);
+            }
+            // A block's line is that of its first statement, not of its opening brace.
+            let start = match stmt {
+                Stmt::Block(stmts) if !is_for_loop(stmts) => None,
+                _ => stmt.line(),
+            };
+            self.start_line(start);
+            self.stmt(stmt);
+            self.trailing_comment(next_line);
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes a braced block, starting at the current position and ending after the
+    /// closing brace.
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.depth += 1;
+        let is_empty = stmts.is_empty()
+            && self
+                .comments
+                .front()
+                .is_none_or(|pending| pending.depth < self.depth);
+        if is_empty {
+            self.out.push_str("{}");
+            self.depth -= 1;
+            return;
+        }
+
+        self.out.push('{');
+        self.trailing_comment(stmts.iter().find_map(Stmt::line));
+        self.out.push('\n');
+        self.indent += 1;
+        self.block_start = true;
+        self.stmts(stmts);
+        self.remaining_comments(self.depth);
+        self.indent -= 1;
+        self.depth -= 1;
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.out.push('}');
+    }
+
+    /// Writes the body of an `if`, `else` or loop, which may or may not be a block.
+    fn body(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(stmts) if !is_for_loop(stmts) => self.block(stmts),
+            _ => self.stmt(stmt),
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+                self.out.push(';');
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.out.push_str("if (");
+                self.expr(condition);
+                self.out.push_str(") ");
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.out.push_str(" else ");
+                    self.body(else_branch);
+                }
+            }
+            Stmt::Print { expression, .. } => {
+                self.out.push_str("print ");
+                self.expr(expression);
+                self.out.push(';');
+            }
+            Stmt::Var { name, initializer } => {
+                self.out.push_str("var ");
+                self.out.push_str(&name.lexeme);
+                if !matches!(initializer, Expr::Literal(Literal::None)) {
+                    self.out.push_str(" = ");
+                    self.expr(initializer);
+                }
+                self.out.push(';');
+            }
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+            } => {
+                if keyword.kind == TokenKind::For {
+                    self.for_loop(None, condition, body);
+                } else {
+                    self.out.push_str("while (");
+                    self.expr(condition);
+                    self.out.push_str(") ");
+                    self.body(body);
+                }
+            }
+            Stmt::Block(stmts) => match stmts.as_slice() {
+                [initializer, Stmt::While {
+                    condition, body, ..
+                }] if is_for_loop(stmts) => self.for_loop(Some(initializer), condition, body),
+                _ => self.block(stmts),
+            },
+            Stmt::Function { name, params, body } => {
+                self.out.push_str("fun ");
+                self.out.push_str(&name.lexeme);
+                self.params(params);
+                self.out.push(' ');
+                self.block(body);
+            }
+            Stmt::Return { value, .. } => {
+                self.out.push_str("return");
+                if !matches!(value, Expr::Literal(Literal::None)) {
+                    self.out.push(' ');
+                    self.expr(value);
+                }
+                self.out.push(';');
+            }
+        }
+    }
+
+    /// Writes a `while` loop desugared from a `for` loop back in its original form.
+    ///
+    /// The parser wraps the body in a block with the increment when there is one. That
+    /// block is only taken apart when the body was itself a block, since otherwise it
+    /// cannot be told apart from a plain block body ending in an expression statement.
+    fn for_loop(&mut self, initializer: Option<&Stmt>, condition: &Expr, body: &Stmt) {
+        self.out.push_str("for (");
+        match initializer {
+            Some(initializer) => self.stmt(initializer),
+            None => self.out.push(';'),
+        }
+        if !matches!(
+            condition,
+            Expr::Literal(Literal::Boolean(LiteralBool::True))
+        ) {
+            self.out.push(' ');
+            self.expr(condition);
+        }
+        self.out.push(';');
+        let body = match body {
+            Stmt::Block(stmts) => match stmts.as_slice() {
+                [body @ Stmt::Block(_), Stmt::Expr(increment)] => {
+                    self.out.push(' ');
+                    self.expr(increment);
+                    body
+                }
+                _ => body,
+            },
+            _ => body,
+        };
+        self.out.push_str(") ");
+        self.body(body);
+    }
+
+    fn params(&mut self, params: &[Token]) {
+        let names: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+        self.out.push('(');
+        self.out.push_str(&names.join(", "));
+        self.out.push(')');
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value } => {
+                self.out.push_str(&name.lexeme);
+                match value.as_ref() {
+                    // Compound assignments are desugared with an operator token that
+                    // did not come from the source, which has no literal.
+                    Expr::Binary {
+                        left,
+                        operator,
+                        right,
+                    } if matches!(operator.literal, token::Literal::None)
+                        && matches!(left.as_ref(), Expr::Variable { name: target } if target.lexeme == name.lexeme) =>
+                    {
+                        self.out.push(' ');
+                        self.out.push_str(&operator.lexeme);
+                        self.out.push_str("= ");
+                        self.expr(right);
+                    }
+                    _ => {
+                        self.out.push_str(" = ");
+                        self.expr(value);
+                    }
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => {
+                self.expr(left);
+                self.out.push(' ');
+                self.out.push_str(&operator.lexeme);
+                self.out.push(' ');
+                self.expr(right);
+            }
+            Expr::Grouping { expression } => {
+                self.out.push('(');
+                self.expr(expression);
+                self.out.push(')');
+            }
+            Expr::Literal(Literal::Number(value)) => {
+                match self
+                    .numbers
+                    .get_mut(&value.to_bits())
+                    .and_then(VecDeque::pop_front)
+                {
+                    Some(lexeme) => self.out.push_str(&lexeme),
+                    None => self.out.push_str(&value.to_string()),
+                }
+            }
+            Expr::Literal(literal) => self.out.push_str(&literal.to_string()),
+            Expr::Unary { operator, right } => {
+                self.out.push_str(&operator.lexeme);
+                self.expr(right);
+            }
+            Expr::Variable { name } => self.out.push_str(&name.lexeme),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.out.push_str(" ? ");
+                self.expr(then_branch);
+                self.out.push_str(" : ");
+                self.expr(else_branch);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                self.out.push('(');
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expr(argument);
+                }
+                self.out.push(')');
+            }
+            Expr::Lambda {
+                keyword,
+                params,
+                body,
+            } => {
+                if keyword.kind == TokenKind::Arrow {
+                    self.params(params);
+                    self.out.push_str(" => ");
+                    match body.as_slice() {
+                        [Stmt::Return {
+                            keyword: arrow,
+                            value,
+                        }] if arrow.kind == TokenKind::Arrow => self.expr(value),
+                        _ => self.block(body),
+                    }
+                } else {
+                    self.out.push_str("fun ");
+                    self.params(params);
+                    self.out.push(' ');
+                    self.block(body);
+                }
+            }
+        }
+    }
+}
+
+/// Whether a block is a `for` loop with an initializer, as desugared by the parser.
+fn is_for_loop(stmts: &[Stmt]) -> bool {
+    matches!(
+        stmts,
+        [Stmt::Var { .. } | Stmt::Expr(_), Stmt::While { keyword, .. }] if keyword.kind == TokenKind::For
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::test_case::TestCase;
+
+    fn parse(source: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens();
+        let stmts = Parser::new(tokens).parse().expect("parsing failed");
+        stmts.iter().map(Stmt::to_string).collect()
+    }
+
+    #[test]
+    fn test_format_source() {
+        let test_cases = vec![
+            TestCase {
+                input: "var   x=1+2*3 ;print x ;",
+                expected: "var x = 1 + 2 * 3;\nprint x;\n",
+            },
+            TestCase {
+                input: "var x; x+=1; x = x - 1; print -(x ** 2) ~/ 3 % 2;",
+                expected: "var x;\nx += 1;\nx = x - 1;\nprint -(x ** 2) ~/ 3 % 2;\n",
+            },
+            TestCase {
+                input: "if(a and !b){print 1;}else if (c) print 2; else {}",
+                expected: "if (a and !b) {\n  print 1;\n} else if (c) print 2; else {}\n",
+            },
+            TestCase {
+                input: "for(var i=0;i<3;i=i+1){print i;} for(;;) print 1; while(x)x=nil;",
+                expected: "for (var i = 0; i < 3; i = i + 1) {\n  print i;\n}\n\
+                           for (;;) print 1;\nwhile (x) x = nil;\n",
+            },
+            TestCase {
+                input: "fun add(a,b){return a+b;} fun f(){return;}\nvar g=(x)=>x?1:nil??2;\nvar h=fun(){print add(1,2);};",
+                expected: "fun add(a, b) {\n  return a + b;\n}\nfun f() {\n  return;\n}\n\
+                           var g = (x) => x ? 1 : nil ?? 2;\n\
+                           var h = fun () {\n  print add(1, 2);\n};\n",
+            },
+            TestCase {
+                input: "print 0xFF+1_000+0b101; print 1e400;",
+                expected: "print 0xFF + 1_000 + 0b101;\nprint 1e400;\n",
+            },
+            TestCase {
+                input: "// header\n\n\nvar x;\n{ // open\n  print 1; // one\n\n  // before two\n  print 2;\n  // end\n} // close\n// footer",
+                expected: "// header\n\nvar x;\n{ // open\n  print 1; // one\n\n  // before two\n  print 2;\n  // end\n} // close\n// footer\n",
+            },
+        ];
+
+        for case in test_cases {
+            assert_eq!(
+                format_source(case.input).unwrap(),
+                case.expected,
+                "Failed on input: {}",
+                case.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_formatting_is_idempotent_and_preserves_ast() {
+        let sources = [
+            "var a=1;// one\n  // two\n\nfun f(x){if(x>1){return x*f(x-1);}\n// nothing\nreturn 1;}",
+            "for (var i = 0; i < 10; i += 1) { // loop\n if (i % 2 == 0) continue_(); else { print i; } }",
+            "for (;; i += 1) print i;\nfor (x = 0; x < 1;) {}\n{ var i = 0; while (i < 1) i = i + 1; }",
+            "var f = (a, b) => {\n// body\nreturn a + b;\n};\nprint f(1, 2) ?? fun () {}();\n",
+            "print \"a\" + \"b\"; print 1.5 + 0x10 + 1_000 + 2e3 + -true;\n\n\n// trailing comment",
+            "if (a)\n  // odd\n  print 1;\nelse\n  print 2; // two\nprint a ? b ? 1 : 2 : (c = 3);",
+            "print 0xFF + 255 + 1_000 + 0b101;\nprint 1e400 - -1e400;",
+        ];
+
+        for source in sources {
+            let formatted = format_source(source).unwrap();
+            assert_eq!(
+                parse(&formatted),
+                parse(source),
+                "Failed on input: {}",
+                source
+            );
+            assert_eq!(
+                format_source(&formatted).unwrap(),
+                formatted,
+                "Failed on input: {}",
+                source
+            );
+            assert_eq!(
+                formatted.matches("//").count(),
+                source.matches("//").count(),
+                "Failed on input: {}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_source() {
+        for source in ["print 1", "var x = @;", "\"unterminated"] {
+            assert!(
+                format_source(source).is_err(),
+                "Failed on input: {}",
+                source
+            );
+        }
+    }
+}
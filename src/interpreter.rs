@@ -1,33 +1,145 @@
 use crate::{
+    callable::{Callable, Function},
+    capabilities::Capabilities,
+    coverage::Coverage,
+    debugger::Debugger,
     environment::Environment,
-    error::RuntimeError,
+    error::{ErrorKind, RuntimeError, Unwind},
     expr::{Expr, Literal, LiteralBool},
-    lit, runtime_err, stmt,
+    limits::{self, CancellationToken, Limits, Meter},
+    lit, natives,
+    profiler::Profiler,
+    runtime_err, stmt,
     token::{Token, TokenKind},
+    trace::Tracer,
 };
 use anyhow::Result;
 use std::io::Write;
+use std::rc::Rc;
 
 pub struct Interpreter<'a, W: Write> {
     pub environment: Environment,
     pub output: &'a mut W,
+    debugger: Option<Debugger>,
+    meter: Meter,
+    capabilities: Capabilities,
+    coverage: Option<Coverage>,
+    profiler: Option<Profiler>,
+    tracer: Option<Tracer>,
 }
 
 impl<'a, W: Write> Interpreter<'a, W> {
-    pub fn new(environment: Environment, output: &'a mut W) -> Self {
+    pub fn new(mut environment: Environment, output: &'a mut W) -> Self {
+        natives::define_globals(&mut environment);
         Interpreter {
             environment,
             output,
+            debugger: None,
+            meter: Meter::default(),
+            capabilities: Capabilities::default(),
+            coverage: None,
+            profiler: None,
+            tracer: None,
         }
     }
+
+    /// Runs every following program under the given debugger.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Applies the given limits to every following program.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.meter.set_limits(limits);
+    }
+
+    /// Records coverage of every following program. The coverage must have been built
+    /// from the syntax tree that runs.
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    /// Stops recording coverage, and returns what was recorded.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Profiles every following program.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops profiling, and returns the profile of the programs run so far.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Traces every following program.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing, and returns the tracer.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Sets what the natives of every following program may do. Every capability is
+    /// allowed by default.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Returns a token that stops the running program, or the next one to run, when
+    /// cancelled from any thread. The token is spent once it has stopped a run.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.meter.cancellation_token()
+    }
+
+    /// Discards every variable and function by replacing the environment with a fresh one.
+    pub fn reset(&mut self) {
+        self.environment = Environment::default();
+        natives::define_globals(&mut self.environment);
+        self.meter.clear_memory();
+    }
+
+    /// Runs a program. Function calls recurse on the native stack, so programs that
+    /// call deeply need a thread with a stack of
+    /// [`STACK_SIZE`](crate::limits::STACK_SIZE) bytes, as the `rlox` binary runs them on.
     pub fn interpret(&mut self, stmts: &[stmt::Stmt]) -> Result<(), RuntimeError> {
-        for stmt in stmts {
-            self.execute_stmt(stmt)?;
+        self.meter.start();
+        let result = stmts.iter().try_for_each(|stmt| self.execute_stmt(stmt));
+        if let Some(profiler) = &mut self.profiler {
+            profiler.pause();
+        }
+        match result {
+            Ok(()) => Ok(()),
+            Err(Unwind::Error(err)) => Err(err),
+            Err(Unwind::Return(_)) => Err(runtime_err!("Can't return from top-level code.", None)),
         }
-        Ok(())
     }
 
-    fn execute_stmt(&mut self, stmt: &stmt::Stmt) -> Result<(), RuntimeError> {
+    /// Evaluates an expression as a whole program, such as a line of REPL input.
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Literal, RuntimeError> {
+        self.meter.start();
+        self.evaluate_expr(expr)
+    }
+
+    fn execute_stmt(&mut self, stmt: &stmt::Stmt) -> Result<(), Unwind> {
+        self.meter.count_statement()?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_stmt(stmt);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record_stmt(stmt);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.stmt(stmt);
+        }
+        if let Some(debugger) = &mut self.debugger {
+            debugger.before_stmt(stmt, &mut self.environment)?;
+        }
+
         match stmt {
             stmt::Stmt::Expr(expression) => {
                 self.evaluate_expr(expression)?;
@@ -37,11 +149,12 @@ impl<'a, W: Write> Interpreter<'a, W> {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => {
                 self.if_stmt(condition, then_branch, else_branch.as_deref())?;
                 Ok(())
             }
-            stmt::Stmt::Print(expression) => {
+            stmt::Stmt::Print { expression, .. } => {
                 let value = self.evaluate_expr(expression)?;
                 writeln!(self.output, "{}", value.printable()).map_err(|_| {
                     runtime_err!(
@@ -59,14 +172,25 @@ impl<'a, W: Write> Interpreter<'a, W> {
                 self.block_stmt(stmts)?;
                 Ok(())
             }
-            stmt::Stmt::While { condition, body } => {
+            stmt::Stmt::While {
+                condition, body, ..
+            } => {
                 self.while_stmt(condition, body)?;
                 Ok(())
             }
+            stmt::Stmt::Function { name, params, body } => {
+                let function = self.function(Some(name), params, body);
+                self.define(name, function)?;
+                Ok(())
+            }
+            stmt::Stmt::Return { value, .. } => {
+                let value = self.evaluate_expr(value)?;
+                Err(Unwind::Return(value))
+            }
         }
     }
 
-    fn evaluate_expr(&mut self, expr: &Expr) -> Result<Literal, RuntimeError> {
+    pub(crate) fn evaluate_expr(&mut self, expr: &Expr) -> Result<Literal, RuntimeError> {
         match expr {
             Expr::Literal(literal) => Ok(literal.clone()),
             Expr::Grouping { expression } => self.evaluate_expr(expression),
@@ -83,6 +207,17 @@ impl<'a, W: Write> Interpreter<'a, W> {
                 operator,
                 right,
             } => self.logical(operator, left, right),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => self.conditional(condition, then_branch, else_branch),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => self.call(callee, paren, arguments),
+            Expr::Lambda { params, body, .. } => Ok(self.function(None, params, body)),
         }
     }
 
@@ -91,9 +226,12 @@ impl<'a, W: Write> Interpreter<'a, W> {
         condition: &Expr,
         then_branch: &stmt::Stmt,
         else_branch: Option<&stmt::Stmt>,
-    ) -> Result<Literal, RuntimeError> {
-        let condition = self.evaluate_expr(condition)?;
-        if is_truthy(&condition) {
+    ) -> Result<Literal, Unwind> {
+        let value = self.evaluate_expr(condition)?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_condition(condition, is_truthy(&value));
+        }
+        if is_truthy(&value) {
             self.execute_stmt(then_branch)?;
         } else if let Some(else_branch) = else_branch {
             self.execute_stmt(else_branch)?;
@@ -101,7 +239,7 @@ impl<'a, W: Write> Interpreter<'a, W> {
         Ok(Literal::None)
     }
 
-    fn while_stmt(&mut self, condition: &Expr, body: &stmt::Stmt) -> Result<Literal, RuntimeError> {
+    fn while_stmt(&mut self, condition: &Expr, body: &stmt::Stmt) -> Result<Literal, Unwind> {
         let mut cond = self.evaluate_expr(condition)?;
         while is_truthy(&cond) {
             let stmt = body;
@@ -111,13 +249,176 @@ impl<'a, W: Write> Interpreter<'a, W> {
         Ok(Literal::None)
     }
 
-    fn block_stmt(&mut self, stmts: &[stmt::Stmt]) -> Result<Literal, RuntimeError> {
+    fn block_stmt(&mut self, stmts: &[stmt::Stmt]) -> Result<Literal, Unwind> {
+        self.meter.enter_scope(None)?;
         self.environment.add_scope();
-        for stmt in stmts {
-            self.execute_stmt(stmt)?;
+        // Pop the scope even when a `return` or an error unwinds through the block.
+        let result = stmts.iter().try_for_each(|stmt| self.execute_stmt(stmt));
+        let captured = self.environment.is_captured();
+        let scope = self.environment.pop_scope();
+        self.meter.exit_scope(limits::scope_bytes(&scope), captured);
+        result.map(|_| Literal::None)
+    }
+
+    /// Creates a function value closing over the current environment.
+    fn function(
+        &mut self,
+        name: Option<&Token>,
+        params: &[Token],
+        body: &Rc<Vec<stmt::Stmt>>,
+    ) -> Literal {
+        Literal::Callable(Callable::Function(Rc::new(Function {
+            name: name.map(|name| name.lexeme.clone()),
+            params: params.to_vec(),
+            body: Rc::clone(body),
+            closure: self.environment.capture(),
+        })))
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expr,
+        paren: &Token,
+        arguments: &[Expr],
+    ) -> Result<Literal, RuntimeError> {
+        let callee = self.evaluate_expr(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate_expr(argument))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Literal::Callable(callable) = callee else {
+            return Err(runtime_err!(
+                "Can only call functions.",
+                Some(paren.clone())
+            ));
+        };
+
+        if arguments.len() != callable.arity() {
+            return Err(runtime_err!(
+                format!(
+                    "Expected {} arguments but got {}.",
+                    callable.arity(),
+                    arguments.len()
+                ),
+                Some(paren.clone())
+            ));
+        }
+
+        match callable {
+            Callable::Function(function) => {
+                self.meter.enter_call(paren)?;
+                // Only the profiler and the tracer report the name.
+                let name = if self.profiler.is_some() || self.tracer.is_some() {
+                    function.report_name()
+                } else {
+                    String::new()
+                };
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter(&name);
+                }
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.enter(paren.line, &name, &arguments);
+                }
+                let result = self.call_function(&function, arguments, paren);
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.exit(paren.line, &name, &result);
+                }
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+                self.meter.exit_call();
+                result
+            }
+            Callable::Native(native) => {
+                if let Some(capability) = native.capability {
+                    if !self.capabilities.allows(capability) {
+                        return Err(runtime_err!(
+                            ErrorKind::PermissionDenied,
+                            format!(
+                                "Can't call '{}': the {} capability is disabled.",
+                                native.name, capability
+                            ),
+                            Some(paren.clone())
+                        ));
+                    }
+                }
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter(native.name);
+                }
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.enter(paren.line, native.name, &arguments);
+                }
+                let result = (native.function)(&arguments);
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.exit(paren.line, native.name, &result);
+                }
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                }
+                let value = result.map_err(|mut err| {
+                    err.token.get_or_insert_with(|| paren.clone());
+                    err
+                })?;
+                if let Literal::String(string) = &value {
+                    self.meter.check(string.len(), Some(paren))?;
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    /// Runs a function body in a new scope on top of the function's closure,
+    /// restoring the caller's environment afterwards.
+    fn call_function(
+        &mut self,
+        function: &Function,
+        arguments: Vec<Literal>,
+        paren: &Token,
+    ) -> Result<Literal, RuntimeError> {
+        let bytes = function
+            .params
+            .iter()
+            .zip(&arguments)
+            .map(|(param, argument)| limits::variable_bytes(&param.lexeme, argument))
+            .sum::<usize>();
+        self.meter.enter_scope(Some(paren))?;
+        if let Err(err) = self.meter.allocate(bytes, Some(paren)) {
+            self.meter.exit_scope(0, false);
+            return Err(err);
+        }
+
+        let mut environment = function.closure.capture();
+        environment.add_scope();
+        for (param, argument) in function.params.iter().zip(arguments) {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.define(param.line, &param.lexeme, &argument);
+            }
+            environment.define(param.lexeme.clone(), argument);
+        }
+
+        if let Some(debugger) = &mut self.debugger {
+            let name = function.name.as_deref().unwrap_or("<lambda>");
+            debugger.enter_function(name, &self.environment);
+        }
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = function
+            .body
+            .iter()
+            .try_for_each(|stmt| self.execute_stmt(stmt));
+        let mut environment = std::mem::replace(&mut self.environment, previous);
+        let captured = environment.is_captured();
+        let scope = environment.pop_scope();
+        self.meter.exit_scope(limits::scope_bytes(&scope), captured);
+        if let Some(debugger) = &mut self.debugger {
+            debugger.exit_function();
+        }
+
+        match result {
+            Ok(()) => Ok(Literal::None),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
         }
-        self.environment.pop_scope();
-        Ok(Literal::None)
     }
 
     fn unary(&mut self, operator: &Token, right: &Expr) -> Result<Literal, RuntimeError> {
@@ -165,6 +466,9 @@ impl<'a, W: Write> Interpreter<'a, W> {
                 TokenKind::Minus => Ok(lit!(l - r)),
                 TokenKind::Slash => Ok(lit!(l / r)),
                 TokenKind::Star => Ok(lit!(l * r)),
+                TokenKind::Percent => Ok(lit!(l % r)),
+                TokenKind::StarStar => Ok(lit!(l.powf(*r))),
+                TokenKind::TildeSlash => Ok(lit!((l / r).floor())),
                 _ => Err(runtime_err!(
                     format!(
                         "Unsupported binary operator {:?} for numbers",
@@ -175,10 +479,13 @@ impl<'a, W: Write> Interpreter<'a, W> {
             },
             (Literal::String(l), Literal::String(r)) => match operator.kind {
                 TokenKind::Plus => {
+                    self.meter.check(l.len() + r.len(), Some(operator))?;
                     let mut value = l.clone();
                     value.push_str(r);
                     Ok(lit!(value))
                 }
+                TokenKind::BangEqual => Ok(lit!(l != r)),
+                TokenKind::EqualEqual => Ok(lit!(l == r)),
                 _ => Err(runtime_err!(
                     format!(
                         "Unsupported binary operator {:?} for strings",
@@ -203,18 +510,37 @@ impl<'a, W: Write> Interpreter<'a, W> {
 
     fn var_stmt(&mut self, name: &Token, initializer: &Expr) -> Result<Literal, RuntimeError> {
         let value = self.evaluate_expr(initializer)?;
-        self.environment.define(name.lexeme.clone(), value);
+        self.define(name, value)?;
         Ok(Literal::None)
     }
 
+    /// Defines a variable in the innermost scope, counting the memory it takes.
+    fn define(&mut self, name: &Token, value: Literal) -> Result<(), RuntimeError> {
+        self.meter
+            .allocate(limits::variable_bytes(&name.lexeme, &value), Some(name))?;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.define(name.line, &name.lexeme, &value);
+        }
+        if let Some(old) = self.environment.define(name.lexeme.clone(), value) {
+            self.meter.free(limits::variable_bytes(&name.lexeme, &old));
+        }
+        Ok(())
+    }
+
     fn var_expr(&mut self, name: &Token) -> Result<Literal, RuntimeError> {
-        self.environment.get(name).cloned()
+        self.environment.get(name)
     }
 
     fn assign(&mut self, name: &Token, value: &Expr) -> Result<Literal, RuntimeError> {
         let value = self.evaluate_expr(value)?;
-        self.environment.assign(name, &value)?;
-        Ok(Literal::None)
+        let bytes = limits::value_bytes(&value);
+        self.meter.check(bytes, Some(name))?;
+        let old = self.environment.assign(name, &value)?;
+        self.meter.replace(limits::value_bytes(&old), bytes);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.assign(name.line, &name.lexeme, &value);
+        }
+        Ok(value)
     }
 
     fn logical(
@@ -225,25 +551,41 @@ impl<'a, W: Write> Interpreter<'a, W> {
     ) -> Result<Literal, RuntimeError> {
         let left_val = self.evaluate_expr(left)?;
 
-        match operator.kind {
-            TokenKind::And => {
-                if !is_truthy(&left_val) {
-                    Ok(left_val)
-                } else {
-                    self.evaluate_expr(right)
-                }
-            }
-            TokenKind::Or => {
-                if is_truthy(&left_val) {
-                    Ok(left_val)
-                } else {
-                    self.evaluate_expr(right)
-                }
+        let evaluate_right = match operator.kind {
+            TokenKind::And => is_truthy(&left_val),
+            TokenKind::Or => !is_truthy(&left_val),
+            TokenKind::QuestionQuestion => left_val == Literal::None,
+            _ => {
+                return Err(runtime_err!(
+                    format!("Unsupported logical operator: {:?}", operator.kind),
+                    Some(operator.clone())
+                ))
             }
-            _ => Err(runtime_err!(
-                format!("Unsupported logical operator: {:?}", operator.kind),
-                Some(operator.clone())
-            )),
+        };
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_logical(operator, evaluate_right);
+        }
+        if evaluate_right {
+            self.evaluate_expr(right)
+        } else {
+            Ok(left_val)
+        }
+    }
+
+    fn conditional(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> Result<Literal, RuntimeError> {
+        let value = self.evaluate_expr(condition)?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_condition(condition, is_truthy(&value));
+        }
+        if is_truthy(&value) {
+            self.evaluate_expr(then_branch)
+        } else {
+            self.evaluate_expr(else_branch)
         }
     }
 }
@@ -261,7 +603,7 @@ impl<'a, W: Write> Interpreter<'a, W> {
 /// # Returns
 /// - `true` if the given literal is truthy.
 /// - `false` otherwise.
-fn is_truthy(literal: &Literal) -> bool {
+pub(crate) fn is_truthy(literal: &Literal) -> bool {
     match literal {
         Literal::Boolean(b) => matches!(b, LiteralBool::True),
         Literal::None => false,
@@ -297,6 +639,8 @@ mod tests {
             literal,
             lexeme: "".to_string(),
             line: 0,
+            column: 0,
+            offset: 0,
         }
     }
 
@@ -338,6 +682,46 @@ mod tests {
                 },
                 expected: lit!("error"),
             },
+            test_case::TestCase {
+                input: Expr::binary(
+                    Expr::Literal(lit!(7.0)),
+                    token(TokenKind::Percent, Literal::None),
+                    Expr::Literal(lit!(3.0)),
+                ),
+                expected: lit!(1.0),
+            },
+            test_case::TestCase {
+                input: Expr::binary(
+                    Expr::Literal(lit!(2.0)),
+                    token(TokenKind::StarStar, Literal::None),
+                    Expr::Literal(lit!(0.5)),
+                ),
+                expected: lit!(2f64.sqrt()),
+            },
+            test_case::TestCase {
+                input: Expr::binary(
+                    Expr::Literal(lit!(-7.0)),
+                    token(TokenKind::TildeSlash, Literal::None),
+                    Expr::Literal(lit!(2.0)),
+                ),
+                expected: lit!(-4.0),
+            },
+            test_case::TestCase {
+                input: Expr::binary(
+                    Expr::Literal(lit!("a")),
+                    token(TokenKind::Percent, Literal::None),
+                    Expr::Literal(lit!(2.0)),
+                ),
+                expected: lit!("error"),
+            },
+            test_case::TestCase {
+                input: Expr::binary(
+                    Expr::Literal(lit!(true)),
+                    token(TokenKind::StarStar, Literal::None),
+                    Expr::Literal(lit!(2.0)),
+                ),
+                expected: lit!("error"),
+            },
         ];
 
         for test_case in test_cases {
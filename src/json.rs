@@ -0,0 +1,589 @@
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    expr::{Expr, Literal, LiteralBool},
+    stmt::Stmt,
+    token::{self, Token, TokenKind, KEYWORD_MAP},
+};
+
+/// Serializes parsed statements to JSON.
+///
+/// Every node is an object with a `type` naming its `Stmt` or `Expr` variant, its source
+/// position, and one field per variant field. The position is the `line`, `column` and
+/// byte `offset` of the node's keyword, name or operator, as the scanner counts them.
+/// Names are plain strings, operators are their lexemes, and function parameters are
+/// `{"name", "line", "column", "offset"}` objects.
+/// Lambdas and returns keep the lexeme of their keyword, `=>` for the arrow form.
+/// Numbers JSON cannot hold are objects such as `{"number": "inf"}`.
+///
+/// Literals and groupings carry no token of their own, so they report the position of
+/// their first child or of the nearest enclosing node; it is `null` only when no such
+/// node has one, as in a top-level `1;`.
+pub fn to_json(stmts: &[Stmt]) -> Value {
+    Value::Array(stmts.iter().map(|stmt| stmt_to_json(stmt, None)).collect())
+}
+
+/// Loads statements from JSON produced by [`to_json`].
+pub fn from_json(value: &Value) -> Result<Vec<Stmt>> {
+    array(value, "program")?
+        .iter()
+        .map(stmt_from_json)
+        .collect()
+}
+
+fn node(kind: &str, token: Option<&Token>, fields: Value) -> Value {
+    let mut node = Map::new();
+    node.insert("type".to_string(), json!(kind));
+    insert_position(&mut node, token);
+    if let Value::Object(fields) = fields {
+        node.extend(fields);
+    }
+    Value::Object(node)
+}
+
+fn insert_position(object: &mut Map<String, Value>, token: Option<&Token>) {
+    object.insert("line".to_string(), json!(token.map(|token| token.line)));
+    object.insert("column".to_string(), json!(token.map(|token| token.column)));
+    object.insert("offset".to_string(), json!(token.map(|token| token.offset)));
+}
+
+fn stmts_to_json(stmts: &[Stmt], parent: Option<&Token>) -> Value {
+    Value::Array(
+        stmts
+            .iter()
+            .map(|stmt| stmt_to_json(stmt, parent))
+            .collect(),
+    )
+}
+
+fn params_to_json(params: &[Token]) -> Value {
+    Value::Array(
+        params
+            .iter()
+            .map(|param| {
+                json!({
+                    "name": param.lexeme,
+                    "line": param.line,
+                    "column": param.column,
+                    "offset": param.offset,
+                })
+            })
+            .collect(),
+    )
+}
+
+fn stmt_to_json(stmt: &Stmt, parent: Option<&Token>) -> Value {
+    let token = stmt.token().or(parent);
+    match stmt {
+        Stmt::Expr(expression) => node(
+            "Expr",
+            token,
+            json!({ "expression": expr_to_json(expression, token) }),
+        ),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => node(
+            "If",
+            token,
+            json!({
+                "condition": expr_to_json(condition, token),
+                "then_branch": stmt_to_json(then_branch, token),
+                "else_branch": else_branch.as_ref().map(|branch| stmt_to_json(branch, token)),
+            }),
+        ),
+        Stmt::Print { expression, .. } => node(
+            "Print",
+            token,
+            json!({ "expression": expr_to_json(expression, token) }),
+        ),
+        Stmt::Var { name, initializer } => node(
+            "Var",
+            token,
+            json!({
+                "name": name.lexeme,
+                "initializer": expr_to_json(initializer, token),
+            }),
+        ),
+        Stmt::While {
+            condition, body, ..
+        } => node(
+            "While",
+            token,
+            json!({
+                "condition": expr_to_json(condition, token),
+                "body": stmt_to_json(body, token),
+            }),
+        ),
+        Stmt::Block(stmts) => node(
+            "Block",
+            token,
+            json!({ "statements": stmts_to_json(stmts, token) }),
+        ),
+        Stmt::Function { name, params, body } => node(
+            "Function",
+            token,
+            json!({
+                "name": name.lexeme,
+                "params": params_to_json(params),
+                "body": stmts_to_json(body, token),
+            }),
+        ),
+        Stmt::Return { keyword, value } => node(
+            "Return",
+            token,
+            json!({
+                "keyword": keyword.lexeme,
+                "value": expr_to_json(value, token),
+            }),
+        ),
+    }
+}
+
+fn expr_to_json(expr: &Expr, parent: Option<&Token>) -> Value {
+    let token = expr.token().or(parent);
+    match expr {
+        Expr::Assign { name, value } => node(
+            "Assign",
+            token,
+            json!({ "name": name.lexeme, "value": expr_to_json(value, token) }),
+        ),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => node(
+            "Binary",
+            token,
+            json!({
+                "operator": operator.lexeme,
+                "left": expr_to_json(left, token),
+                "right": expr_to_json(right, token),
+            }),
+        ),
+        Expr::Grouping { expression } => node(
+            "Grouping",
+            token,
+            json!({ "expression": expr_to_json(expression, token) }),
+        ),
+        Expr::Literal(literal) => node(
+            "Literal",
+            token,
+            json!({ "value": literal_to_json(literal) }),
+        ),
+        Expr::Unary { operator, right } => node(
+            "Unary",
+            token,
+            json!({ "operator": operator.lexeme, "right": expr_to_json(right, token) }),
+        ),
+        Expr::Variable { name } => node("Variable", token, json!({ "name": name.lexeme })),
+        Expr::Logical {
+            left,
+            operator,
+            right,
+        } => node(
+            "Logical",
+            token,
+            json!({
+                "operator": operator.lexeme,
+                "left": expr_to_json(left, token),
+                "right": expr_to_json(right, token),
+            }),
+        ),
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => node(
+            "Conditional",
+            token,
+            json!({
+                "condition": expr_to_json(condition, token),
+                "then_branch": expr_to_json(then_branch, token),
+                "else_branch": expr_to_json(else_branch, token),
+            }),
+        ),
+        Expr::Call {
+            callee, arguments, ..
+        } => node(
+            "Call",
+            token,
+            json!({
+                "callee": expr_to_json(callee, token),
+                "arguments": arguments
+                    .iter()
+                    .map(|argument| expr_to_json(argument, token))
+                    .collect::<Vec<_>>(),
+            }),
+        ),
+        Expr::Lambda {
+            keyword,
+            params,
+            body,
+        } => node(
+            "Lambda",
+            token,
+            json!({
+                "keyword": keyword.lexeme,
+                "params": params_to_json(params),
+                "body": stmts_to_json(body, token),
+            }),
+        ),
+    }
+}
+
+fn literal_to_json(literal: &Literal) -> Value {
+    match literal {
+        Literal::Number(n) if n.is_finite() => json!(n),
+        Literal::Number(n) => json!({ "number": n.to_string().to_lowercase() }),
+        Literal::String(s) | Literal::Identifier(s) => json!(s),
+        Literal::Boolean(b) => json!(*b == LiteralBool::True),
+        // Function values only exist at runtime and never appear in a parsed program.
+        Literal::Callable(callable) => json!(callable.to_string()),
+        Literal::None => Value::Null,
+    }
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value> {
+    value
+        .get(name)
+        .ok_or_else(|| anyhow!("Missing field '{}' in {}", name, value))
+}
+
+fn string<'a>(value: &'a Value, name: &str) -> Result<&'a str> {
+    field(value, name)?
+        .as_str()
+        .ok_or_else(|| anyhow!("Field '{}' must be a string in {}", name, value))
+}
+
+fn array<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<Value>> {
+    value
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected an array for {}, got {}", name, value))
+}
+
+fn number(value: &Value, name: &str) -> Result<usize> {
+    field(value, name)?
+        .as_u64()
+        .map(|number| number as usize)
+        .ok_or_else(|| anyhow!("Field '{}' must be a number in {}", name, value))
+}
+
+/// Builds a token the way the scanner would for the given kind and lexeme, at the
+/// position of the node `value`.
+fn token(kind: TokenKind, lexeme: &str, value: &Value) -> Result<Token> {
+    Ok(Token {
+        kind,
+        lexeme: lexeme.to_string(),
+        literal: token::Literal::String(lexeme.to_string()),
+        line: number(value, "line")?,
+        column: number(value, "column")?,
+        offset: number(value, "offset")?,
+    })
+}
+
+fn identifier(value: &Value, name: &str) -> Result<Token> {
+    token(TokenKind::Identifier, string(value, name)?, value)
+}
+
+/// The keyword of a lambda or return, which is `=>` in the arrow form and `kind`
+/// otherwise.
+fn keyword(value: &Value, kind: TokenKind) -> Result<Token> {
+    let lexeme = string(value, "keyword")?;
+    let kind = match lexeme {
+        "=>" => TokenKind::Arrow,
+        _ if KEYWORD_MAP.get(lexeme) == Some(&kind) => kind,
+        _ => return Err(anyhow!("Invalid keyword '{}' in {}", lexeme, value)),
+    };
+    token(kind, lexeme, value)
+}
+
+fn operator(value: &Value) -> Result<Token> {
+    let lexeme = string(value, "operator")?;
+    let kind = match lexeme {
+        "-" => TokenKind::Minus,
+        "+" => TokenKind::Plus,
+        "/" => TokenKind::Slash,
+        "*" => TokenKind::Star,
+        "%" => TokenKind::Percent,
+        "**" => TokenKind::StarStar,
+        "~/" => TokenKind::TildeSlash,
+        "!" => TokenKind::Bang,
+        "!=" => TokenKind::BangEqual,
+        "==" => TokenKind::EqualEqual,
+        ">" => TokenKind::Greater,
+        ">=" => TokenKind::GreaterEqual,
+        "<" => TokenKind::Less,
+        "<=" => TokenKind::LessEqual,
+        "??" => TokenKind::QuestionQuestion,
+        _ => *KEYWORD_MAP
+            .get(lexeme)
+            .filter(|kind| matches!(kind, TokenKind::And | TokenKind::Or))
+            .ok_or_else(|| anyhow!("Unknown operator '{}'", lexeme))?,
+    };
+    token(kind, lexeme, value)
+}
+
+fn params_from_json(value: &Value) -> Result<Vec<Token>> {
+    array(field(value, "params")?, "params")?
+        .iter()
+        .map(|param| identifier(param, "name"))
+        .collect()
+}
+
+fn body_from_json(value: &Value) -> Result<Rc<Vec<Stmt>>> {
+    Ok(Rc::new(from_json(field(value, "body")?)?))
+}
+
+fn boxed_stmt(value: &Value, name: &str) -> Result<Box<Stmt>> {
+    Ok(Box::new(stmt_from_json(field(value, name)?)?))
+}
+
+fn boxed_expr(value: &Value, name: &str) -> Result<Box<Expr>> {
+    Ok(Box::new(expr_from_json(field(value, name)?)?))
+}
+
+fn stmt_from_json(value: &Value) -> Result<Stmt> {
+    let stmt = match string(value, "type")? {
+        "Expr" => Stmt::Expr(expr_from_json(field(value, "expression")?)?),
+        "If" => Stmt::If {
+            keyword: token(TokenKind::If, "if", value)?,
+            condition: expr_from_json(field(value, "condition")?)?,
+            then_branch: boxed_stmt(value, "then_branch")?,
+            else_branch: match field(value, "else_branch")? {
+                Value::Null => None,
+                branch => Some(Box::new(stmt_from_json(branch)?)),
+            },
+        },
+        "Print" => Stmt::Print {
+            keyword: token(TokenKind::Print, "print", value)?,
+            expression: expr_from_json(field(value, "expression")?)?,
+        },
+        "Var" => Stmt::Var {
+            name: identifier(value, "name")?,
+            initializer: expr_from_json(field(value, "initializer")?)?,
+        },
+        "While" => Stmt::While {
+            keyword: token(TokenKind::While, "while", value)?,
+            condition: expr_from_json(field(value, "condition")?)?,
+            body: boxed_stmt(value, "body")?,
+        },
+        "Block" => Stmt::Block(from_json(field(value, "statements")?)?),
+        "Function" => Stmt::Function {
+            name: identifier(value, "name")?,
+            params: params_from_json(value)?,
+            body: body_from_json(value)?,
+        },
+        "Return" => Stmt::Return {
+            keyword: keyword(value, TokenKind::Return)?,
+            value: expr_from_json(field(value, "value")?)?,
+        },
+        kind => return Err(anyhow!("Unknown statement type '{}'", kind)),
+    };
+    Ok(stmt)
+}
+
+fn expr_from_json(value: &Value) -> Result<Expr> {
+    let expr = match string(value, "type")? {
+        "Assign" => Expr::Assign {
+            name: identifier(value, "name")?,
+            value: boxed_expr(value, "value")?,
+        },
+        "Binary" => Expr::Binary {
+            left: boxed_expr(value, "left")?,
+            operator: operator(value)?,
+            right: boxed_expr(value, "right")?,
+        },
+        "Grouping" => Expr::Grouping {
+            expression: boxed_expr(value, "expression")?,
+        },
+        "Literal" => Expr::Literal(literal_from_json(field(value, "value")?)?),
+        "Unary" => Expr::Unary {
+            operator: operator(value)?,
+            right: boxed_expr(value, "right")?,
+        },
+        "Variable" => Expr::Variable {
+            name: identifier(value, "name")?,
+        },
+        "Logical" => Expr::Logical {
+            left: boxed_expr(value, "left")?,
+            operator: operator(value)?,
+            right: boxed_expr(value, "right")?,
+        },
+        "Conditional" => Expr::Conditional {
+            condition: boxed_expr(value, "condition")?,
+            then_branch: boxed_expr(value, "then_branch")?,
+            else_branch: boxed_expr(value, "else_branch")?,
+        },
+        "Call" => Expr::Call {
+            callee: boxed_expr(value, "callee")?,
+            paren: token(TokenKind::RightParen, ")", value)?,
+            arguments: array(field(value, "arguments")?, "arguments")?
+                .iter()
+                .map(expr_from_json)
+                .collect::<Result<_>>()?,
+        },
+        "Lambda" => Expr::Lambda {
+            keyword: keyword(value, TokenKind::Fun)?,
+            params: params_from_json(value)?,
+            body: body_from_json(value)?,
+        },
+        kind => return Err(anyhow!("Unknown expression type '{}'", kind)),
+    };
+    Ok(expr)
+}
+
+fn literal_from_json(value: &Value) -> Result<Literal> {
+    match value {
+        Value::Null => Ok(Literal::None),
+        Value::Bool(b) => Ok((*b).into()),
+        Value::Number(n) => n
+            .as_f64()
+            .map(Literal::Number)
+            .ok_or_else(|| anyhow!("Invalid number literal {}", n)),
+        Value::String(s) => Ok(s.as_str().into()),
+        Value::Object(number) => match number.get("number").and_then(Value::as_str) {
+            Some("inf") => Ok(Literal::Number(f64::INFINITY)),
+            Some("-inf") => Ok(Literal::Number(f64::NEG_INFINITY)),
+            Some("nan") => Ok(Literal::Number(f64::NAN)),
+            _ => Err(anyhow!("Invalid number literal {}", value)),
+        },
+        _ => Err(anyhow!("Invalid literal value {}", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{formatter::format_expr, parser::Parser, scanner::Scanner};
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens();
+        Parser::new(tokens).parse().expect("parsing failed")
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json(&parse("var x = 1;\nprint -x;"));
+        assert_eq!(
+            json,
+            json!([
+                {
+                    "type": "Var",
+                    "line": 1,
+                    "column": 5,
+                    "offset": 4,
+                    "name": "x",
+                    "initializer": {
+                        "type": "Literal",
+                        "line": 1,
+                        "column": 5,
+                        "offset": 4,
+                        "value": 1.0,
+                    },
+                },
+                {
+                    "type": "Print",
+                    "line": 2,
+                    "column": 1,
+                    "offset": 11,
+                    "expression": {
+                        "type": "Unary",
+                        "line": 2,
+                        "column": 7,
+                        "offset": 17,
+                        "operator": "-",
+                        "right": {
+                            "type": "Variable",
+                            "line": 2,
+                            "column": 8,
+                            "offset": 18,
+                            "name": "x",
+                        },
+                    },
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+            var a = nil;
+            fun add(x, y) { return x + y; }
+            var twice = (f, x) => f(f(x));
+            var negate = (x) => { return -x; };
+            for (var i = 0; i < 3; i += 1) {
+                if (i % 2 == 0 and !false) print "even"; else print i ** 2;
+            }
+            while (a ? true : false) { a = a or "x"; }
+            print (1 ~/ 2) >= -3 ?? fun () { return; };
+            print 1e400 - -1e400;
+        "#;
+        let stmts = parse(source);
+
+        let json = to_json(&stmts);
+        let loaded = from_json(&json).expect("loading failed");
+
+        let display = |stmts: &[Stmt]| stmts.iter().map(Stmt::to_string).collect::<Vec<_>>();
+        assert_eq!(display(&loaded), display(&stmts));
+        assert_eq!(to_json(&loaded), json);
+
+        let positions = |stmts: &[Stmt]| {
+            stmts
+                .iter()
+                .map(|stmt| {
+                    stmt.token()
+                        .map(|token| (token.line, token.column, token.offset))
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&loaded), positions(&stmts));
+        assert_eq!(positions(&loaded)[2], Some((4, 17, 86)));
+
+        // Display shows neither the arrow form nor how numbers are spelled.
+        let initializers = |stmts: &[Stmt]| {
+            stmts
+                .iter()
+                .filter_map(|stmt| match stmt {
+                    Stmt::Var { initializer, .. } => Some(format_expr(initializer)),
+                    Stmt::Print { expression, .. } => Some(format_expr(expression)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let formatted = initializers(&loaded);
+        assert_eq!(formatted, initializers(&stmts));
+        assert_eq!(formatted[2], "(x) => {\n  return -x;\n}");
+        assert_eq!(formatted[4], "inf - -inf");
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        for json in [
+            json!({}),
+            json!([{ "type": "Nope", "line": 1 }]),
+            json!([{ "type": "Print", "line": 1 }]),
+            json!([{ "type": "Var", "line": "one", "name": "x", "initializer": null }]),
+            json!([{
+                "type": "Expr",
+                "line": 1,
+                "expression": {
+                    "type": "Binary",
+                    "line": 1,
+                    "operator": "<>",
+                    "left": { "type": "Literal", "line": 1, "value": 1 },
+                    "right": { "type": "Literal", "line": 1, "value": 2 },
+                },
+            }]),
+        ] {
+            assert!(from_json(&json).is_err(), "Failed on json: {}", json);
+        }
+    }
+}
@@ -1,19 +1,40 @@
+mod callable;
 mod error;
-mod expr;
-mod scanner;
-mod stmt;
+mod natives;
 mod utils;
 
+pub mod capabilities;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
 pub mod environment;
+pub mod expr;
+pub mod formatter;
 pub mod interpreter;
+pub mod json;
+pub mod limits;
+pub mod lint;
+pub mod lsp;
 pub mod parser;
+pub mod profiler;
+pub mod repl;
+pub mod scanner;
+pub mod stmt;
+pub mod testing;
 pub mod token;
+pub mod trace;
 
-use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner, token::TokenKind};
-use std::io::Write;
+pub use crate::error::{ErrorKind, RuntimeError};
+
+use crate::{
+    coverage::Coverage, interpreter::Interpreter, parser::Parser, profiler::Profiler,
+    scanner::Scanner, token::TokenKind, trace::Tracer,
+};
+use std::io::{self, Write};
 
 pub struct RLox<'a, W: Write> {
     pub had_error: bool,
+    pub had_runtime_error: bool,
     interpreter: Interpreter<'a, W>,
 }
 
@@ -21,32 +42,148 @@ impl<'a, W: Write> RLox<'a, W> {
     pub fn new(interpreter: Interpreter<'a, W>) -> Self {
         RLox {
             had_error: false,
+            had_runtime_error: false,
             interpreter,
         }
     }
 
     pub fn run(&mut self, source: &str) {
+        self.run_program(source, false);
+    }
+
+    /// Runs a program as with [`RLox::run`], recording which lines and branches it
+    /// executes. Returns `None` if the program did not parse.
+    pub fn run_with_coverage(&mut self, source: &str) -> Option<Coverage> {
+        self.run_program(source, true)
+    }
+
+    fn run_program(&mut self, source: &str, record_coverage: bool) -> Option<Coverage> {
         let mut scanner = Scanner::new(source);
         let tokens = scanner.scan_tokens();
         let mut parser = Parser::new(tokens);
         let stmts = parser.parse();
 
         match stmts {
-            Ok(stmts) => match self.interpreter.interpret(&stmts) {
-                Ok(_) => {}
-                Err(err) => eprintln!(
-                    "{}\n{} ",
-                    err.message,
-                    err.token
-                        .map(|token| format!("[line {}]", token.line))
-                        .unwrap_or("".to_string())
-                ),
+            // The scanner has already reported its errors.
+            Ok(_) if !scanner.errors().is_empty() => self.had_error = true,
+            Ok(stmts) => {
+                if record_coverage {
+                    self.interpreter.set_coverage(Coverage::new(&stmts));
+                }
+                if let Err(err) = self.interpreter.interpret(&stmts) {
+                    self.had_runtime_error = true;
+                    report_runtime_error(err);
+                }
+                // The coverage refers to the syntax tree, so it can't outlive it.
+                return self.interpreter.take_coverage();
+            }
+            Err(err) => {
+                self.had_error = true;
+                eprintln!("{}", err);
+            }
+        }
+        None
+    }
+
+    /// Stops profiling, and returns the profile of the programs run so far.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.interpreter.take_profiler()
+    }
+
+    /// Stops tracing, and returns the tracer to finish writing the trace.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.interpreter.take_tracer()
+    }
+
+    /// Runs a chunk of REPL input.
+    ///
+    /// Input that is a single expression, with or without a trailing `;`, is evaluated
+    /// and its value written to the output. Anything else runs as with [`RLox::run`].
+    pub fn run_repl(&mut self, source: &str) {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens();
+        let mut parser = Parser::new(tokens);
+
+        match parser.parse_expression() {
+            // The scanner has already reported its errors.
+            Some(Ok(_)) if !scanner.errors().is_empty() => self.had_error = true,
+            Some(Ok(expr)) => match self.interpreter.evaluate(&expr) {
+                Ok(value) => {
+                    if let Err(err) = writeln!(self.interpreter.output, "{}", value.printable()) {
+                        eprintln!("failed to write output for expr: {}: {}", expr, err);
+                    }
+                }
+                Err(err) => {
+                    self.had_runtime_error = true;
+                    report_runtime_error(err);
+                }
             },
-            Err(err) => eprintln!("{}", err),
+            Some(Err(err)) => {
+                self.had_error = true;
+                eprintln!("{}", err);
+            }
+            None => self.run(source),
+        }
+    }
+}
+
+/// Writes every token scanned from `source` to `output`, one per line.
+pub fn dump_tokens(source: &str, output: &mut impl Write) -> io::Result<()> {
+    let mut scanner = Scanner::new(source);
+    scanner
+        .scan_tokens()
+        .iter()
+        .try_for_each(|token| writeln!(output, "{}", token))
+}
+
+/// Writes the statements parsed from `source` to `output` in their s-expression form,
+/// one per line. Returns `Ok(false)` if the source could not be parsed.
+pub fn dump_ast(source: &str, output: &mut impl Write) -> io::Result<bool> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+    match Parser::new(tokens).parse() {
+        Ok(stmts) => {
+            for stmt in stmts {
+                writeln!(output, "{}", stmt)?;
+            }
+            Ok(true)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(false)
         }
     }
 }
 
+/// Writes the statements parsed from `source` to `output` as pretty-printed JSON,
+/// in the format described by [`json::to_json`]. Returns `Ok(false)` if the source
+/// could not be parsed.
+pub fn dump_json_ast(source: &str, output: &mut impl Write) -> io::Result<bool> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+    match Parser::new(tokens).parse() {
+        Ok(stmts) => {
+            serde_json::to_writer_pretty(&mut *output, &json::to_json(&stmts))?;
+            writeln!(output)?;
+            Ok(true)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Ok(false)
+        }
+    }
+}
+
+fn report_runtime_error(err: RuntimeError) {
+    eprintln!(
+        "{}\n{} ",
+        err.message,
+        err.token
+            .map(|token| format!("[line {}]", token.line))
+            .unwrap_or("".to_string())
+    );
+}
+
 pub fn error_token(token: &token::Token, message: &str) -> bool {
     if token.kind == TokenKind::Eof {
         report(token.line, " at end", message)
@@ -60,6 +197,6 @@ pub fn error_line(line: usize, message: &str) {
 }
 
 pub fn report(line: usize, location: &str, message: &str) -> bool {
-    println!("[line {}] Error {} : {}", line, location, message);
+    eprintln!("[line {}] Error{}: {}", line, location, message);
     true
 }
@@ -0,0 +1,255 @@
+//! Limits on the work a program may do, and cancellation of running programs, for
+//! running untrusted scripts.
+
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    callable::{Callable, Function},
+    error::{ErrorKind, RuntimeError},
+    expr::Literal,
+    runtime_err,
+    token::Token,
+};
+
+/// The bytes charged for a new scope.
+pub(crate) const SCOPE_BYTES: usize = size_of::<Rc<HashMap<String, Literal>>>();
+/// The bytes charged for defining a variable, besides its name.
+pub(crate) const VARIABLE_BYTES: usize = size_of::<(String, Literal)>();
+/// The bytes charged for a function value, besides its parameters.
+pub(crate) const FUNCTION_BYTES: usize = size_of::<Function>();
+
+/// The bytes charged for a value stored in a variable.
+pub(crate) fn value_bytes(value: &Literal) -> usize {
+    match value {
+        Literal::String(string) => string.len(),
+        Literal::Callable(Callable::Function(function)) => {
+            FUNCTION_BYTES + size_of_val(function.params.as_slice())
+        }
+        _ => 0,
+    }
+}
+
+/// The bytes charged for a variable holding `value`.
+pub(crate) fn variable_bytes(name: &str, value: &Literal) -> usize {
+    VARIABLE_BYTES + name.len() + value_bytes(value)
+}
+
+/// The bytes charged for the variables of a scope.
+pub(crate) fn scope_bytes(scope: &HashMap<String, Literal>) -> usize {
+    scope
+        .iter()
+        .map(|(name, value)| variable_bytes(name, value))
+        .sum()
+}
+
+/// The deepest that function calls can nest, whatever the limits. Each call recurses
+/// on the native stack, so deeper calls would overflow it and abort the process.
+pub const MAX_CALL_DEPTH: usize = 1024;
+/// The native stack size a thread running programs needs so that calls nested
+/// [`MAX_CALL_DEPTH`] deep fit, even in a debug build.
+pub const STACK_SIZE: usize = 256 << 20;
+
+/// Caps on one run of a program, that is one call to
+/// [`Interpreter::interpret`](crate::interpreter::Interpreter::interpret). Every limit
+/// is off by default.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    /// The maximum number of statements executed.
+    pub max_statements: Option<u64>,
+    /// The maximum wall-clock time.
+    pub timeout: Option<Duration>,
+    /// The maximum number of nested function calls. Calls never nest deeper than
+    /// [`MAX_CALL_DEPTH`], the safe maximum on a thread with a [`STACK_SIZE`] stack, so
+    /// a larger value, like no value, leaves only that bound.
+    pub max_call_depth: Option<usize>,
+    /// The maximum number of bytes in use by the variables and scopes alive, with the
+    /// strings and functions they hold. A block or call scope is freed when it ends,
+    /// unless a closure captured it, in which case it is freed with the enclosing
+    /// scope. Temporary values are not counted, but none may be larger than what is
+    /// left.
+    pub max_memory: Option<usize>,
+}
+
+/// Stops a running program from another thread. Clones share the same state.
+///
+/// A token stops a single run: the one running when it is cancelled, or else the next
+/// one, before its first statement. Once a run stops because of it, the interpreter
+/// takes a fresh token for the runs after it, so get a new token for each run.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Asks the program to stop. It stops with an [`ErrorKind::Cancelled`] error before
+    /// its next statement, after unwinding its scopes.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tracks a running program's use of what [`Limits`] caps, and whether it was cancelled.
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    limits: Limits,
+    cancellation: CancellationToken,
+    statements: u64,
+    deadline: Option<Instant>,
+    call_depth: usize,
+    /// The bytes in use. Globals stay in use from one run to the next.
+    memory: usize,
+    /// The bytes of the captured scopes kept alive by each open block or call scope,
+    /// innermost last. Scopes captured at the top level stay in use.
+    scopes: Vec<usize>,
+}
+
+impl Meter {
+    pub(crate) fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub(crate) fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Starts a new run of a program, with no statements executed yet.
+    pub(crate) fn start(&mut self) {
+        self.statements = 0;
+        self.scopes.clear();
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Counts a statement about to run, and fails if the program was cancelled or has
+    /// run out of statements or time.
+    pub(crate) fn count_statement(&mut self) -> Result<(), RuntimeError> {
+        if self.cancellation.is_cancelled() {
+            self.cancellation = CancellationToken::default();
+            return Err(runtime_err!(
+                ErrorKind::Cancelled,
+                "Execution was cancelled.",
+                None
+            ));
+        }
+        self.statements += 1;
+        if let Some(max) = self.limits.max_statements {
+            if self.statements > max {
+                return Err(runtime_err!(
+                    ErrorKind::StatementLimit,
+                    format!("Exceeded the limit of {} executed statements.", max),
+                    None
+                ));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() > deadline {
+                return Err(runtime_err!(
+                    ErrorKind::Timeout,
+                    format!("Exceeded the time limit of {} ms.", timeout.as_millis()),
+                    None
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Counts a function call about to start. Every successful call must be matched
+    /// by [`Meter::exit_call`].
+    pub(crate) fn enter_call(&mut self, paren: &Token) -> Result<(), RuntimeError> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(runtime_err!(
+                ErrorKind::CallDepth,
+                "Stack overflow.",
+                Some(paren.clone())
+            ));
+        }
+        if let Some(max) = self.limits.max_call_depth {
+            if self.call_depth >= max {
+                return Err(runtime_err!(
+                    ErrorKind::CallDepth,
+                    format!("Exceeded the maximum call depth of {}.", max),
+                    Some(paren.clone())
+                ));
+            }
+        }
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    /// Frees everything, when the variables are discarded.
+    pub(crate) fn clear_memory(&mut self) {
+        self.memory = 0;
+        self.scopes.clear();
+    }
+
+    /// Counts a new block or call scope. Every successful call must be matched by
+    /// [`Meter::exit_scope`].
+    pub(crate) fn enter_scope(&mut self, token: Option<&Token>) -> Result<(), RuntimeError> {
+        self.allocate(SCOPE_BYTES, token)?;
+        self.scopes.push(0);
+        Ok(())
+    }
+
+    /// Frees the innermost scope, whose variables take `bytes`, along with the captured
+    /// scopes it kept alive. If a closure `captured` the scope, it stays in use until
+    /// the enclosing scope is freed instead.
+    pub(crate) fn exit_scope(&mut self, bytes: usize, captured: bool) {
+        let Some(kept) = self.scopes.pop() else {
+            return;
+        };
+        let bytes = SCOPE_BYTES + bytes + kept;
+        match (captured, self.scopes.last_mut()) {
+            (true, Some(enclosing)) => *enclosing += bytes,
+            (true, None) => {}
+            (false, _) => self.free(bytes),
+        }
+    }
+
+    /// Fails if a value of `bytes` would not fit in the memory left.
+    pub(crate) fn check(&self, bytes: usize, token: Option<&Token>) -> Result<(), RuntimeError> {
+        match self.limits.max_memory {
+            Some(max) if self.memory.saturating_add(bytes) > max => Err(runtime_err!(
+                ErrorKind::OutOfMemory,
+                format!("Out of memory: exceeded the limit of {} bytes.", max),
+                token.cloned()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts memory about to be put in use, and fails without counting it if the
+    /// program would go over its limit.
+    pub(crate) fn allocate(
+        &mut self,
+        bytes: usize,
+        token: Option<&Token>,
+    ) -> Result<(), RuntimeError> {
+        self.check(bytes, token)?;
+        self.memory += bytes;
+        Ok(())
+    }
+
+    /// Counts memory no longer in use.
+    pub(crate) fn free(&mut self, bytes: usize) {
+        self.memory = self.memory.saturating_sub(bytes);
+    }
+
+    /// Counts a variable's value of `old` bytes having been replaced by one of `new`
+    /// bytes, which [`Meter::check`] found room for.
+    pub(crate) fn replace(&mut self, old: usize, new: usize) {
+        self.memory = self.memory.saturating_sub(old) + new;
+    }
+}
@@ -0,0 +1,603 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    expr::{Expr, Literal, LiteralBool},
+    parser::Parser,
+    scanner::{Comment, Scanner},
+    stmt::Stmt,
+    token::Token,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A lint rule and the severity it is reported with unless configured otherwise.
+#[derive(Debug)]
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+}
+
+pub const UNUSED_VARIABLE: &str = "unused-variable";
+pub const SHADOWED_VARIABLE: &str = "shadowed-variable";
+pub const UNREACHABLE_CODE: &str = "unreachable-code";
+pub const ASSIGNMENT_IN_CONDITION: &str = "assignment-in-condition";
+pub const NIL_ASSIGNMENT_IN_CONDITION: &str = "nil-assignment-in-condition";
+pub const CONSTANT_CONDITION: &str = "constant-condition";
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: UNUSED_VARIABLE,
+        severity: Severity::Warning,
+        description: "A local variable or function is declared but never read.",
+    },
+    Rule {
+        id: SHADOWED_VARIABLE,
+        severity: Severity::Warning,
+        description: "A declaration in a nested scope hides one from an enclosing scope.",
+    },
+    Rule {
+        id: UNREACHABLE_CODE,
+        severity: Severity::Warning,
+        description: "A statement follows a `return` and can never run.",
+    },
+    Rule {
+        id: ASSIGNMENT_IN_CONDITION,
+        severity: Severity::Warning,
+        description: "An `if` condition is an assignment, which is often a mistyped `==`.",
+    },
+    Rule {
+        id: NIL_ASSIGNMENT_IN_CONDITION,
+        severity: Severity::Error,
+        description: "A condition assigns `nil`, which is always false; `== nil` was meant.",
+    },
+    Rule {
+        id: CONSTANT_CONDITION,
+        severity: Severity::Warning,
+        description: "A `while` condition does not depend on anything that can change.",
+    },
+];
+
+/// Looks up a rule by its ID.
+pub fn rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == id)
+}
+
+/// A problem found by a lint rule.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}]: {}",
+            self.line, self.severity, self.rule, self.message
+        )
+    }
+}
+
+/// Per-rule severity overrides, usually read from a config file.
+///
+/// The config file has one `rule-id = level` setting per line, where the level is
+/// `allow` to disable the rule, `warning` or `error`. Lines starting with `#` are
+/// comments.
+#[derive(Debug, Default)]
+pub struct Config {
+    levels: HashMap<&'static str, Option<Severity>>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config> {
+        let mut config = Config::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (id, level) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expect 'rule = level' on line {}.", i + 1))?;
+            let rule = rule(id.trim())
+                .ok_or_else(|| anyhow!("Unknown lint rule '{}' on line {}.", id.trim(), i + 1))?;
+            let level = match level.trim() {
+                "allow" => None,
+                "warning" => Some(Severity::Warning),
+                "error" => Some(Severity::Error),
+                level => {
+                    return Err(anyhow!(
+                        "Unknown level '{}' on line {}, expect allow, warning or error.",
+                        level,
+                        i + 1
+                    ))
+                }
+            };
+            config.levels.insert(rule.id, level);
+        }
+
+        Ok(config)
+    }
+
+    /// The severity a rule is reported with, or `None` if it is disabled.
+    pub fn severity(&self, rule: &Rule) -> Option<Severity> {
+        match self.levels.get(rule.id) {
+            Some(level) => *level,
+            None => Some(rule.severity),
+        }
+    }
+}
+
+/// Runs every enabled rule over `source` and returns the diagnostics ordered by line.
+///
+/// A `// lint:allow(rule, ...)` comment disables the listed rules on its own line when
+/// it follows code, and otherwise on the next line of code.
+pub fn lint_source(source: &str, config: &Config) -> Result<Vec<Diagnostic>> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().clone();
+    if let Some(error) = scanner.errors().first() {
+        return Err(anyhow!("{}", error));
+    }
+    let stmts = Parser::new(&tokens).parse()?;
+
+    let mut linter = Linter {
+        config,
+        scopes: vec![HashMap::new()],
+        diagnostics: Vec::new(),
+    };
+    linter.stmts(&stmts);
+
+    let allowed = allowed_rules(scanner.comments(), &tokens);
+    let mut diagnostics: Vec<Diagnostic> = linter
+        .diagnostics
+        .into_iter()
+        .filter(|diagnostic| !allowed.contains(&(diagnostic.line, diagnostic.rule.to_string())))
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    Ok(diagnostics)
+}
+
+/// Collects the `(line, rule)` pairs disabled by `lint:allow` comments.
+fn allowed_rules(comments: &[Comment], tokens: &[Token]) -> HashSet<(usize, String)> {
+    let mut allowed = HashSet::new();
+
+    for comment in comments {
+        let Some(rules) = comment
+            .text
+            .trim_start_matches('/')
+            .trim()
+            .strip_prefix("lint:allow(")
+            .and_then(|rest| rest.split_once(')'))
+            .map(|(rules, _)| rules)
+        else {
+            continue;
+        };
+        let line = if comment.trailing {
+            comment.line
+        } else {
+            match tokens.get(comment.next_token) {
+                Some(token) => token.line,
+                None => continue,
+            }
+        };
+        for rule in rules.split(',') {
+            allowed.insert((line, rule.trim().to_string()));
+        }
+    }
+
+    allowed
+}
+
+struct Variable {
+    line: usize,
+    used: bool,
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    /// The declarations of each enclosing scope, globals first.
+    scopes: Vec<HashMap<String, Variable>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, id: &'static str, line: usize, message: String) {
+        let rule = rule(id).expect("unknown lint rule");
+        if let Some(severity) = self.config.severity(rule) {
+            self.diagnostics.push(Diagnostic {
+                rule: rule.id,
+                severity,
+                line,
+                message,
+            });
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("no scope to end");
+        let mut unused: Vec<(String, Variable)> = scope
+            .into_iter()
+            .filter(|(name, variable)| !variable.used && !name.starts_with('_'))
+            .collect();
+        unused.sort_by_key(|(_, variable)| variable.line);
+        for (name, variable) in unused {
+            self.report(
+                UNUSED_VARIABLE,
+                variable.line,
+                format!("'{}' is declared but never used.", name),
+            );
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if self.scopes.len() > 1 {
+            let enclosing = self.scopes[..self.scopes.len() - 1]
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme));
+            if let Some(shadowed) = enclosing {
+                let message = format!(
+                    "'{}' shadows the declaration on line {}.",
+                    name.lexeme, shadowed.line
+                );
+                self.report(SHADOWED_VARIABLE, name.line, message);
+            }
+        }
+
+        // Globals may be used by code the linter never sees, such as other REPL input.
+        let used = self.scopes.len() == 1;
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare in")
+            .insert(
+                name.lexeme.clone(),
+                Variable {
+                    line: name.line,
+                    used,
+                },
+            );
+    }
+
+    fn use_variable(&mut self, name: &Token) {
+        if let Some(variable) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name.lexeme))
+        {
+            variable.used = true;
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        let mut returned = false;
+        for stmt in stmts {
+            if returned {
+                if let Some(line) = stmt.line() {
+                    self.report(
+                        UNREACHABLE_CODE,
+                        line,
+                        "Unreachable code after 'return'.".to_string(),
+                    );
+                }
+                returned = false;
+            }
+            self.stmt(stmt);
+            returned |= always_returns(stmt);
+        }
+    }
+
+    fn function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.begin_scope();
+        for param in params {
+            self.scopes.last_mut().unwrap().insert(
+                param.lexeme.clone(),
+                Variable {
+                    line: param.line,
+                    used: true,
+                },
+            );
+        }
+        self.stmts(body);
+        self.end_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr)
+            | Stmt::Print {
+                expression: expr, ..
+            } => self.expr(expr),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if let Expr::Assign { name, value } = condition {
+                    if !is_nil(value) {
+                        self.report(
+                            ASSIGNMENT_IN_CONDITION,
+                            name.line,
+                            format!(
+                                "Assignment to '{}' used as a condition; use '==' to compare \
+                                 or wrap it in parentheses.",
+                                name.lexeme
+                            ),
+                        );
+                    }
+                }
+                self.condition(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.expr(initializer);
+                self.declare(name);
+            }
+            Stmt::While {
+                keyword,
+                condition,
+                body,
+            } => {
+                // `while (true)` is the usual way to loop forever, and is what `for (;;)`
+                // desugars to.
+                let is_true = matches!(
+                    condition,
+                    Expr::Literal(Literal::Boolean(LiteralBool::True))
+                );
+                if is_constant(condition) && !is_true {
+                    self.report(
+                        CONSTANT_CONDITION,
+                        keyword.line,
+                        format!("The loop condition '{}' is constant.", condition),
+                    );
+                }
+                self.condition(condition);
+                self.stmt(body);
+            }
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                self.stmts(stmts);
+                self.end_scope();
+            }
+            Stmt::Function { name, params, body } => {
+                self.declare(name);
+                self.function(params, body);
+            }
+            Stmt::Return { value, .. } => self.expr(value),
+        }
+    }
+
+    /// Checks an expression whose truthiness is tested, then walks it. Unlike other
+    /// assignments, parentheses do not make assigning `nil` in a condition intentional.
+    fn condition(&mut self, condition: &Expr) {
+        let mut inner = condition;
+        while let Expr::Grouping { expression } = inner {
+            inner = expression;
+        }
+        if let Expr::Assign { name, value } = inner {
+            if is_nil(value) {
+                self.report(
+                    NIL_ASSIGNMENT_IN_CONDITION,
+                    name.line,
+                    format!(
+                        "'{} = nil' is always false; did you mean '{} == nil'?",
+                        name.lexeme, name.lexeme
+                    ),
+                );
+            }
+        }
+        self.expr(condition);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { value, .. } => self.expr(value),
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Logical { left, right, .. } => {
+                self.condition(left);
+                self.condition(right);
+            }
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Literal(_) => {}
+            Expr::Unary { operator, right } => {
+                if operator.lexeme == "!" {
+                    self.condition(right);
+                } else {
+                    self.expr(right);
+                }
+            }
+            Expr::Variable { name } => self.use_variable(name),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Lambda { params, body, .. } => self.function(params, body),
+        }
+    }
+}
+
+fn is_nil(expr: &Expr) -> bool {
+    matches!(expr, Expr::Literal(Literal::None))
+}
+
+/// Whether an expression is built only from literals, so it always has the same value.
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => true,
+        Expr::Grouping { expression } => is_constant(expression),
+        Expr::Unary { right, .. } => is_constant(right),
+        Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+            is_constant(left) && is_constant(right)
+        }
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+        } => is_constant(condition) && is_constant(then_branch) && is_constant(else_branch),
+        _ => false,
+    }
+}
+
+/// Whether a statement returns on every path through it.
+fn always_returns(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return { .. } => true,
+        Stmt::Block(stmts) => stmts.iter().any(always_returns),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => always_returns(then_branch) && always_returns(else_branch),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::test_case::TestCase;
+
+    fn lint(source: &str, config: &Config) -> Vec<(&'static str, usize)> {
+        lint_source(source, config)
+            .unwrap()
+            .iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let test_cases = vec![
+            TestCase {
+                input: "var unused = 1;\n{\n  var x = 1;\n  var _y = 2;\n  var z = 3;\n  print z;\n}",
+                expected: vec![(UNUSED_VARIABLE, 3)],
+            },
+            TestCase {
+                input: "fun f(a) {\n  var b;\n  b = a;\n  fun g() {}\n}",
+                expected: vec![(UNUSED_VARIABLE, 2), (UNUSED_VARIABLE, 4)],
+            },
+            TestCase {
+                input: "var a = 1;\n{\n  var a = 2;\n  print a;\n  {\n    var a = 3;\n    print a;\n  }\n}",
+                expected: vec![(SHADOWED_VARIABLE, 3), (SHADOWED_VARIABLE, 6)],
+            },
+            TestCase {
+                input: "fun f(n) {\n  var n = 1;\n  return n;\n}\nfor (var i = 0; i < 1; i += 1) {}\nfor (var i = 0; i < 1; i += 1) {}",
+                expected: vec![],
+            },
+            TestCase {
+                input: "fun f(x) {\n  if (x) return 1; else { return 2; }\n  print x;\n  print x;\n}\nfun g() {\n  return;\n}",
+                expected: vec![(UNREACHABLE_CODE, 3)],
+            },
+            TestCase {
+                input: "var x;\nif (x = 1) print x;\nif ((x = 2)) print x;\nif (x == 1) print x;",
+                expected: vec![(ASSIGNMENT_IN_CONDITION, 2)],
+            },
+            TestCase {
+                input: "var x;\nif (x = nil) print x;\nwhile (x = nil) {}\nprint !(x = nil) or (x = nil);\nx = nil;",
+                expected: vec![
+                    (NIL_ASSIGNMENT_IN_CONDITION, 2),
+                    (NIL_ASSIGNMENT_IN_CONDITION, 3),
+                    (NIL_ASSIGNMENT_IN_CONDITION, 4),
+                    (NIL_ASSIGNMENT_IN_CONDITION, 4),
+                ],
+            },
+            TestCase {
+                input: "while (false) {}\nwhile (1 < 2) {}\nwhile (true) {}\nfor (;;) {}\nvar x;\nwhile (x) {}",
+                expected: vec![(CONSTANT_CONDITION, 1), (CONSTANT_CONDITION, 2)],
+            },
+        ];
+
+        for case in test_cases {
+            assert_eq!(
+                lint(case.input, &Config::default()),
+                case.expected,
+                "Failed on input: {}",
+                case.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_allow_comments() {
+        let source = "{\n  var a = 1; // lint:allow(unused-variable)\n  // lint:allow(shadowed-variable, unused-variable)\n  var b = 1;\n  var c = 1; // lint:allow(shadowed-variable)\n}\nvar b;";
+        assert_eq!(lint(source, &Config::default()), vec![(UNUSED_VARIABLE, 5)]);
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse(
+            "# Project lint settings\nunused-variable = allow\n\nconstant-condition = error\n",
+        )
+        .unwrap();
+        let diagnostics = lint_source("{ var a; }\nwhile (false) {}", &config).unwrap();
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                rule: CONSTANT_CONDITION,
+                severity: Severity::Error,
+                line: 2,
+                message: "The loop condition 'false' is constant.".to_string(),
+            }]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "2: error[constant-condition]: The loop condition 'false' is constant."
+        );
+
+        for text in [
+            "unused-variable",
+            "no-such-rule = allow",
+            "unused-variable = off",
+        ] {
+            assert!(Config::parse(text).is_err(), "Failed on config: {}", text);
+        }
+    }
+}
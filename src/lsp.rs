@@ -0,0 +1,682 @@
+//! A Language Server Protocol server for Lox, spoken over stdin and stdout.
+//!
+//! Documents are kept in memory as they are opened and edited, and are analysed from
+//! scratch with the [`Scanner`] and [`Parser`] whenever a request needs them.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    environment::Environment,
+    expr::{Expr, Literal},
+    formatter,
+    lint::{self, Severity},
+    natives,
+    parser::{ParseError, Parser},
+    scanner::Scanner,
+    stmt::Stmt,
+    token::Token,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const SYMBOL_FUNCTION: u64 = 12;
+const SYMBOL_VARIABLE: u64 = 13;
+
+/// Reads one message framed with a `Content-Length` header. Returns `Ok(None)` at the
+/// end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body).unwrap_or(Value::Null)))
+}
+
+/// Writes one message with a `Content-Length` header.
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serves requests from `input` until an `exit` notification or the end of the input.
+///
+/// Returns whether the client asked the server to shut down before exiting, which
+/// decides the exit code.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            if !message.is_object() {
+                let error = error_response(Value::Null, PARSE_ERROR, "Invalid JSON.");
+                write_message(&mut output, &error)?;
+            }
+            continue;
+        };
+        if method == "exit" {
+            return Ok(server.shut_down);
+        }
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let outgoing = server.handle(method, &params, id.is_some());
+        for notification in outgoing.notifications {
+            write_message(&mut output, &notification)?;
+        }
+        if let Some(id) = id {
+            let response = match outgoing.result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => error_response(id, code, &message),
+            };
+            write_message(&mut output, &response)?;
+        }
+    }
+
+    Ok(server.shut_down)
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// What handling one message produced: a result for requests, and any notifications
+/// to send first.
+struct Outgoing {
+    result: Result<Value, (i64, String)>,
+    notifications: Vec<Value>,
+}
+
+impl From<Result<Value, (i64, String)>> for Outgoing {
+    fn from(result: Result<Value, (i64, String)>) -> Self {
+        Outgoing {
+            result,
+            notifications: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, String>,
+    shut_down: bool,
+}
+
+impl Server {
+    fn handle(&mut self, method: &str, params: &Value, is_request: bool) -> Outgoing {
+        if self.shut_down && is_request {
+            return Err((INVALID_REQUEST, "The server is shutting down.".to_string())).into();
+        }
+
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "rlox", "version": env!("CARGO_PKG_VERSION") },
+            }))
+            .into(),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null).into()
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                match (document["uri"].as_str(), document["text"].as_str()) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Ok(Value::Null).into(),
+                }
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str();
+                // Only full document sync is advertised, so the last change has it all.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Ok(Value::Null).into(),
+                }
+            }
+            "textDocument/didClose" => {
+                let Some(uri) = params["textDocument"]["uri"].as_str() else {
+                    return Ok(Value::Null).into();
+                };
+                self.documents.remove(uri);
+                Outgoing {
+                    result: Ok(Value::Null),
+                    notifications: vec![publish_diagnostics(uri, Vec::new())],
+                }
+            }
+            "textDocument/definition" => self.at_position(params, |analysis, uri, offset| {
+                analysis
+                    .definition_at(offset)
+                    .map(|definition| location(uri, &analysis.text, &definition.token))
+                    .unwrap_or(Value::Null)
+            }),
+            "textDocument/references" => self.at_position(params, |analysis, uri, offset| {
+                let include_declaration = params["context"]["includeDeclaration"]
+                    .as_bool()
+                    .unwrap_or(true);
+                let Some(index) = analysis.definition_index_at(offset) else {
+                    return json!([]);
+                };
+                let mut tokens: Vec<&Token> = Vec::new();
+                if include_declaration {
+                    tokens.push(&analysis.definitions[index].token);
+                }
+                tokens.extend(
+                    analysis
+                        .references
+                        .iter()
+                        .filter(|reference| reference.definition == Some(index))
+                        .map(|reference| &reference.token),
+                );
+                tokens.sort_by_key(|token| token.offset);
+                Value::Array(
+                    tokens
+                        .into_iter()
+                        .map(|token| location(uri, &analysis.text, token))
+                        .collect(),
+                )
+            }),
+            "textDocument/hover" => self.at_position(params, |analysis, _, offset| {
+                let Some(definition) = analysis.definition_at(offset) else {
+                    return Value::Null;
+                };
+                json!({
+                    "contents": {
+                        "kind": "markdown",
+                        "value": format!(
+                            "```lox\n{}\n```\nDeclared on line {}.",
+                            definition.detail, definition.token.line
+                        ),
+                    },
+                })
+            }),
+            "textDocument/documentSymbol" => {
+                let Some(text) = params["textDocument"]["uri"]
+                    .as_str()
+                    .and_then(|uri| self.documents.get(uri))
+                else {
+                    return Err((INVALID_PARAMS, "Unknown document.".to_string())).into();
+                };
+                let analysis = Analysis::new(text);
+                Ok(analysis.symbols(None)).into()
+            }
+            _ if is_request => {
+                Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))).into()
+            }
+            _ => Ok(Value::Null).into(),
+        }
+    }
+
+    /// Stores a document's new text and publishes its diagnostics.
+    fn update(&mut self, uri: &str, text: String) -> Outgoing {
+        let diagnostics = Analysis::new(&text).diagnostics;
+        self.documents.insert(uri.to_string(), text);
+        Outgoing {
+            result: Ok(Value::Null),
+            notifications: vec![publish_diagnostics(uri, diagnostics)],
+        }
+    }
+
+    /// Answers a request about the position in `params` with `f`, given the analysed
+    /// document, its URI and the byte offset of the position.
+    fn at_position(
+        &self,
+        params: &Value,
+        f: impl FnOnce(&Analysis, &str, usize) -> Value,
+    ) -> Outgoing {
+        let uri = params["textDocument"]["uri"].as_str();
+        let Some((uri, text)) = uri.and_then(|uri| Some((uri, self.documents.get(uri)?))) else {
+            return Err((INVALID_PARAMS, "Unknown document.".to_string())).into();
+        };
+        let position = &params["position"];
+        let (Some(line), Some(character)) =
+            (position["line"].as_u64(), position["character"].as_u64())
+        else {
+            return Err((INVALID_PARAMS, "Missing position.".to_string())).into();
+        };
+
+        let analysis = Analysis::new(text);
+        let result = match offset_at(text, line as usize, character as usize) {
+            Some(offset) => f(&analysis, uri, offset),
+            None => Value::Null,
+        };
+        Ok(result).into()
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// Converts a byte offset to an LSP position, which counts UTF-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[..line_start].matches('\n').count();
+    let character = text[line_start..offset].encode_utf16().count();
+    json!({ "line": line, "character": character })
+}
+
+/// Converts an LSP position to a byte offset, or `None` if it is past the last line.
+fn offset_at(text: &str, line: usize, character: usize) -> Option<usize> {
+    let line_start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n').nth(line - 1)?.0 + 1
+    };
+    let line_text = text[line_start..].split('\n').next().unwrap_or("");
+
+    let mut units = 0;
+    for (i, c) in line_text.char_indices() {
+        if units >= character {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_start + line_text.len())
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+fn token_range(text: &str, token: &Token) -> Value {
+    range(text, token.offset, token.offset + token.lexeme.len())
+}
+
+fn location(uri: &str, text: &str, token: &Token) -> Value {
+    json!({ "uri": uri, "range": token_range(text, token) })
+}
+
+/// The range covering a whole line, for diagnostics that only know their line.
+fn line_range(text: &str, line: usize) -> Value {
+    let start = offset_at(text, line.saturating_sub(1), 0).unwrap_or(text.len());
+    let end = start + text[start..].find('\n').unwrap_or(text.len() - start);
+    range(text, start, end)
+}
+
+fn diagnostic(range: Value, severity: Severity, code: Option<&str>, message: &str) -> Value {
+    let severity = match severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut diagnostic = json!({
+        "range": range,
+        "severity": severity,
+        "source": "rlox",
+        "message": message,
+    });
+    if let Some(code) = code {
+        diagnostic["code"] = json!(code);
+    }
+    diagnostic
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DefinitionKind {
+    Variable,
+    Function,
+    Parameter,
+}
+
+/// A declared name: a variable, function or parameter.
+struct Definition {
+    token: Token,
+    kind: DefinitionKind,
+    /// The declaration as shown on hover.
+    detail: String,
+    /// The function whose body declares this name, if any.
+    parent: Option<usize>,
+}
+
+/// A use of a name, and the definition it resolves to if one was found.
+struct Reference {
+    token: Token,
+    definition: Option<usize>,
+}
+
+/// Everything the server knows about one version of a document.
+struct Analysis {
+    text: String,
+    definitions: Vec<Definition>,
+    references: Vec<Reference>,
+    diagnostics: Vec<Value>,
+}
+
+impl Analysis {
+    fn new(text: &str) -> Self {
+        let mut analysis = Analysis {
+            text: text.to_string(),
+            definitions: Vec::new(),
+            references: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+
+        let mut scanner = Scanner::new(text);
+        let tokens = scanner.scan_tokens().clone();
+        for error in scanner.errors() {
+            // The error covers the character it starts at, which may take several bytes.
+            let length = text[error.offset..]
+                .chars()
+                .next()
+                .map_or(0, char::len_utf8);
+            let range = range(text, error.offset, error.offset + length);
+            let diagnostic = diagnostic(range, Severity::Error, None, &error.message);
+            analysis.diagnostics.push(diagnostic);
+        }
+
+        let stmts = match Parser::new(&tokens).parse() {
+            Ok(stmts) => stmts,
+            Err(err) => {
+                let range = match err.downcast_ref::<ParseError>() {
+                    Some(error) => token_range(text, &error.token),
+                    None => line_range(text, 1),
+                };
+                let diagnostic = diagnostic(range, Severity::Error, None, &err.to_string());
+                analysis.diagnostics.push(diagnostic);
+                return analysis;
+            }
+        };
+
+        let mut resolver = Resolver {
+            analysis: &mut analysis,
+            scopes: vec![HashMap::new()],
+            function: None,
+        };
+        resolver.stmts(&stmts);
+        resolver.resolve_globals();
+
+        if let Ok(lints) = lint::lint_source(text, &lint::Config::default()) {
+            for lint in lints {
+                let range = line_range(text, lint.line);
+                let diagnostic = diagnostic(range, lint.severity, Some(lint.rule), &lint.message);
+                analysis.diagnostics.push(diagnostic);
+            }
+        }
+
+        analysis
+    }
+
+    /// The index of the definition of the name at `offset`, whether the name there is
+    /// a declaration or a use.
+    fn definition_index_at(&self, offset: usize) -> Option<usize> {
+        let contains =
+            |token: &Token| token.offset <= offset && offset <= token.offset + token.lexeme.len();
+        self.definitions
+            .iter()
+            .position(|definition| contains(&definition.token))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|reference| contains(&reference.token))
+                    .and_then(|reference| reference.definition)
+            })
+    }
+
+    fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        self.definition_index_at(offset)
+            .map(|index| &self.definitions[index])
+    }
+
+    /// The document symbols for the declarations directly inside `parent`.
+    fn symbols(&self, parent: Option<usize>) -> Value {
+        let symbols = self
+            .definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, definition)| {
+                definition.parent == parent && definition.kind != DefinitionKind::Parameter
+            })
+            .map(|(index, definition)| {
+                let kind = match definition.kind {
+                    DefinitionKind::Function => SYMBOL_FUNCTION,
+                    _ => SYMBOL_VARIABLE,
+                };
+                let range = token_range(&self.text, &definition.token);
+                json!({
+                    "name": definition.token.lexeme,
+                    "detail": definition.detail,
+                    "kind": kind,
+                    "range": range,
+                    "selectionRange": range,
+                    "children": self.symbols(Some(index)),
+                })
+            })
+            .collect();
+        Value::Array(symbols)
+    }
+}
+
+/// Resolves every use of a name to its declaration, following Lox's scoping rules.
+struct Resolver<'a> {
+    analysis: &'a mut Analysis,
+    /// The names declared in each enclosing scope, globals first.
+    scopes: Vec<HashMap<String, usize>>,
+    /// The innermost enclosing named function, as a definition index.
+    function: Option<usize>,
+}
+
+impl Resolver<'_> {
+    fn declare(&mut self, token: &Token, kind: DefinitionKind, detail: String) -> usize {
+        let index = self.analysis.definitions.len();
+        self.analysis.definitions.push(Definition {
+            token: token.clone(),
+            kind,
+            detail,
+            parent: self.function,
+        });
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare in")
+            .insert(token.lexeme.clone(), index);
+        index
+    }
+
+    fn reference(&mut self, token: &Token) {
+        let definition = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&token.lexeme).copied());
+        self.analysis.references.push(Reference {
+            token: token.clone(),
+            definition,
+        });
+    }
+
+    /// Resolves uses of globals that were declared after the code using them, which
+    /// is fine in Lox as long as the declaration runs first, and reports the rest.
+    fn resolve_globals(&mut self) {
+        let mut environment = Environment::default();
+        natives::define_globals(&mut environment);
+        let natives: Vec<String> = environment
+            .globals()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        for reference in &mut self.analysis.references {
+            if reference.definition.is_some() {
+                continue;
+            }
+            reference.definition = self.scopes[0].get(&reference.token.lexeme).copied();
+            if reference.definition.is_none() && !natives.contains(&reference.token.lexeme) {
+                let message = format!("Undefined variable '{}'.", reference.token.lexeme);
+                let range = token_range(&self.analysis.text, &reference.token);
+                let diagnostic = diagnostic(range, Severity::Error, None, &message);
+                self.analysis.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for param in params {
+            let detail = format!("(parameter) {}", param.lexeme);
+            self.declare(param, DefinitionKind::Parameter, detail);
+        }
+        self.stmts(body);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr)
+            | Stmt::Print {
+                expression: expr, ..
+            }
+            | Stmt::Return { value: expr, .. } => self.expr(expr),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.expr(initializer);
+                let detail = match initializer {
+                    Expr::Literal(Literal::None) => format!("var {}", name.lexeme),
+                    _ => format!(
+                        "var {} = {}",
+                        name.lexeme,
+                        formatter::format_expr(initializer)
+                    ),
+                };
+                self.declare(name, DefinitionKind::Variable, detail);
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.expr(condition);
+                self.stmt(body);
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(HashMap::new());
+                self.stmts(stmts);
+                self.scopes.pop();
+            }
+            Stmt::Function { name, params, body } => {
+                let params_list: Vec<&str> =
+                    params.iter().map(|param| param.lexeme.as_str()).collect();
+                let detail = format!("fun {}({})", name.lexeme, params_list.join(", "));
+                let index = self.declare(name, DefinitionKind::Function, detail);
+
+                let enclosing = self.function.replace(index);
+                self.function(params, body);
+                self.function = enclosing;
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value } => {
+                // `x += 1` parses as `x = x + 1` with the same token for both `x`, which
+                // is a single reference.
+                match value.as_ref() {
+                    Expr::Binary { left, right, .. } if matches!(left.as_ref(), Expr::Variable { name: read } if read.offset == name.offset) => {
+                        self.expr(right)
+                    }
+                    _ => self.expr(value),
+                }
+                self.reference(name);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Literal(_) => {}
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name } => self.reference(name),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Lambda { params, body, .. } => self.function(params, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let text = "var é = 1;\nprint é;\n";
+        let offset = text.rfind('é').unwrap();
+        assert_eq!(position(text, offset), json!({ "line": 1, "character": 6 }));
+        assert_eq!(offset_at(text, 1, 6), Some(offset));
+        assert_eq!(offset_at(text, 0, 100), Some(text.find('\n').unwrap()));
+        assert_eq!(offset_at(text, 5, 0), None);
+    }
+}
@@ -1,62 +1,487 @@
+mod cli;
+mod debug_prompt;
+
+use std::cell::Cell;
 use std::env::args;
 use std::fs;
-use std::io::{self, BufRead, Write};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::rc::Rc;
+use std::thread;
+
+use rlox::{
+    coverage,
+    debugger::Debugger,
+    environment::Environment,
+    interpreter::Interpreter,
+    limits::STACK_SIZE,
+    lint,
+    profiler::Profiler,
+    repl::{self, LoxHelper},
+    testing,
+    trace::Tracer,
+    RLox,
+};
+use rustyline::{error::ReadlineError, history::FileHistory, Editor};
 
-use rlox::{environment::Environment, interpreter::Interpreter, RLox};
+use cli::{Command, Input, Mode, Options, TraceOutput};
+use debug_prompt::DebugPrompt;
+
+/// The lint config file used when `--config` is not given.
+const LINT_CONFIG: &str = ".rloxlint";
 
 fn main() {
-    match args().len() {
-        0..=1 => run_prompt(),
-        2 => {
-            let args: Vec<String> = args().collect();
-            run_file(&args[1]);
+    // Function calls recurse on the native stack, which must be large enough for the
+    // deepest calls the interpreter allows.
+    let command = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("failed to start the interpreter thread");
+    // A panic has already been reported; exit with the code it would have on the main
+    // thread.
+    if command.join().is_err() {
+        exit(101);
+    }
+}
+
+fn run_command() {
+    let options = match cli::parse_args(args().skip(1)) {
+        Ok(Some(Command::Run(options))) => options,
+        Ok(Some(Command::Fmt { check, files })) => return format_files(check, &files),
+        Ok(Some(Command::Lint { config, files })) => return lint_files(config, &files),
+        Ok(Some(Command::Lsp)) => match rlox::lsp::run(io::stdin().lock(), io::stdout()) {
+            Ok(true) => return,
+            Ok(false) => exit(1),
+            Err(err) => {
+                eprintln!("Language server failed: {err}");
+                exit(74);
+            }
+        },
+        Ok(Some(Command::Test { paths, coverage })) => return run_tests(&paths, coverage),
+        Ok(Some(Command::Dap)) => match rlox::dap::run(io::stdin().lock(), io::stdout()) {
+            Ok(()) => return,
+            Err(err) => {
+                eprintln!("Debug adapter failed: {err}");
+                exit(74);
+            }
+        },
+        Ok(None) => {
+            println!("{}", cli::USAGE);
+            return;
         }
-        _ => {
-            println!("Usage: rlox [script]");
+        Err(message) => {
+            eprintln!("{message}\n\n{}", cli::USAGE);
             exit(64);
         }
+    };
+
+    let source = match &options.input {
+        Input::Prompt => return run_prompt(),
+        Input::File(file_name) => match fs::read_to_string(file_name) {
+            Ok(content) => content,
+            Err(_) => {
+                eprintln!("Could not open: {file_name}");
+                exit(66);
+            }
+        },
+        Input::Inline(code) => code.clone(),
+    };
+
+    match options.mode {
+        Mode::Run => run_source(&source, &options),
+        Mode::Debug => debug_source(&source),
+        Mode::Tokens => {
+            if let Err(err) = rlox::dump_tokens(&source, &mut io::stdout()) {
+                eprintln!("Could not write tokens: {err}");
+                exit(74);
+            }
+        }
+        Mode::Ast => match rlox::dump_ast(&source, &mut io::stdout()) {
+            Ok(true) => {}
+            Ok(false) => exit(65),
+            Err(err) => {
+                eprintln!("Could not write syntax tree: {err}");
+                exit(74);
+            }
+        },
+        Mode::JsonAst => match rlox::dump_json_ast(&source, &mut io::stdout()) {
+            Ok(true) => {}
+            Ok(false) => exit(65),
+            Err(err) => {
+                eprintln!("Could not write syntax tree: {err}");
+                exit(74);
+            }
+        },
+    }
+}
+
+/// Formats each file in place, or with `check` only reports the files that are not
+/// formatted. Exits with 1 if any file needs formatting in check mode.
+fn format_files(check: bool, files: &[String]) {
+    let mut unformatted = false;
+    for file_name in files {
+        let source = match fs::read_to_string(file_name) {
+            Ok(content) => content,
+            Err(_) => {
+                eprintln!("Could not open: {file_name}");
+                exit(66);
+            }
+        };
+        let formatted = match rlox::formatter::format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("Could not format {file_name}: {err}");
+                exit(65);
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("Not formatted: {file_name}");
+            unformatted = true;
+        } else if let Err(err) = fs::write(file_name, formatted) {
+            eprintln!("Could not write {file_name}: {err}");
+            exit(74);
+        }
+    }
+    if unformatted {
+        exit(1);
+    }
+}
+
+/// Lints each file, printing diagnostics as `file:line: ...`. Exits with 1 if any
+/// diagnostic is an error.
+fn lint_files(config: Option<String>, files: &[String]) {
+    let config_file = config.or_else(|| {
+        Path::new(LINT_CONFIG)
+            .exists()
+            .then(|| LINT_CONFIG.to_string())
+    });
+    let config = match config_file {
+        Some(config_file) => {
+            let parsed = fs::read_to_string(&config_file)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| lint::Config::parse(&text));
+            match parsed {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("Invalid lint config {config_file}: {err}");
+                    exit(78);
+                }
+            }
+        }
+        None => lint::Config::default(),
+    };
+
+    let mut had_error = false;
+    for file_name in files {
+        let source = match fs::read_to_string(file_name) {
+            Ok(content) => content,
+            Err(_) => {
+                eprintln!("Could not open: {file_name}");
+                exit(66);
+            }
+        };
+        let diagnostics = match lint::lint_source(&source, &config) {
+            Ok(diagnostics) => diagnostics,
+            Err(err) => {
+                eprintln!("Could not lint {file_name}: {err}");
+                exit(65);
+            }
+        };
+        for diagnostic in diagnostics {
+            println!("{file_name}:{diagnostic}");
+            had_error |= diagnostic.severity == lint::Severity::Error;
+        }
+    }
+    if had_error {
+        exit(1);
+    }
+}
+
+/// Runs the tests in every test file under `paths`, printing each result and any
+/// failure as `file:line: message`. Exits with 1 if any test fails or none are found.
+///
+/// With a `coverage` file, writes one LCOV record per test file to it and prints the
+/// lines each test file left uncovered and a total.
+fn run_tests(paths: &[String], coverage: Option<String>) {
+    let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+    if let Some(missing) = paths.iter().find(|path| !path.exists()) {
+        eprintln!("Could not open: {}", missing.display());
+        exit(66);
+    }
+    let files = match testing::discover(&paths) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("Could not search for tests: {err}");
+            exit(74);
+        }
+    };
+
+    let (mut passed, mut failed) = (0, 0);
+    let mut lcov = Vec::new();
+    let mut total = coverage::Summary::default();
+    for file in &files {
+        let file_name = file.display();
+        let source = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(_) => {
+                eprintln!("Could not open: {file_name}");
+                exit(66);
+            }
+        };
+        println!("{file_name}");
+        let run = match coverage {
+            Some(_) => testing::run_tests_with_coverage(&source)
+                .map(|(results, coverage)| (results, Some(coverage))),
+            None => testing::run_tests(&source).map(|results| (results, None)),
+        };
+        let (results, file_coverage) = match run {
+            Ok(run) => run,
+            Err(failure) => {
+                println!("  ERROR {}", location(&file_name, &failure));
+                failed += 1;
+                continue;
+            }
+        };
+        for result in results {
+            let Some(failure) = result.failure else {
+                println!("  PASS {}", result.name);
+                passed += 1;
+                continue;
+            };
+            println!("  FAIL {}", result.name);
+            println!("    {}", location(&file_name, &failure));
+            for line in result.output.lines() {
+                println!("    | {line}");
+            }
+            failed += 1;
+        }
+        if let Some(file_coverage) = file_coverage {
+            if let Err(err) = file_coverage.write_lcov(&file_name.to_string(), &mut lcov) {
+                eprintln!("Could not write coverage: {err}");
+                exit(74);
+            }
+            if let Some(uncovered) = uncovered_lines(&file_coverage) {
+                println!("  {uncovered}");
+            }
+            total.add(file_coverage.summary());
+        }
+    }
+
+    if passed + failed == 0 {
+        println!("No tests found.");
+        exit(1);
+    }
+    println!(
+        "\n{} tests: {passed} passed, {failed} failed.",
+        passed + failed
+    );
+    if let Some(lcov_file) = coverage {
+        write_output_file(&lcov_file, &lcov);
+        println!("Coverage: {total}");
+    }
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+fn location(file_name: &impl std::fmt::Display, failure: &testing::Failure) -> String {
+    match failure.line {
+        Some(line) => format!("{file_name}:{line}: {}", failure.message),
+        None => format!("{file_name}: {}", failure.message),
     }
 }
 
-fn run_file(file_name: &str) {
-    if let Ok(content) = fs::read_to_string(file_name) {
-        let environment = Environment::default();
-        let mut stdout = io::stdout();
-        let interpreter = Interpreter::new(environment, &mut stdout);
-        let mut rlox = RLox::new(interpreter);
-        rlox.run(&content);
-        if rlox.had_error {
-            exit(65);
+/// Runs the program, recording its coverage, profile and trace if asked to. These are
+/// still written when the program stops with a runtime error.
+fn run_source(source: &str, options: &Options) {
+    let environment = Environment::default();
+    let mut stdout = io::stdout();
+    let mut interpreter = Interpreter::new(environment, &mut stdout);
+    if options.profile.is_some() {
+        interpreter.set_profiler(Profiler::default());
+    }
+    match &options.trace {
+        Some(TraceOutput::Stderr) => {
+            interpreter.set_tracer(Tracer::new(Box::new(io::stderr())));
         }
-    } else {
-        eprintln!("Could not open: {file_name}");
+        Some(TraceOutput::File(file_name)) => match fs::File::create(file_name) {
+            Ok(file) => interpreter.set_tracer(Tracer::new(Box::new(io::BufWriter::new(file)))),
+            Err(err) => {
+                eprintln!("Could not write {file_name}: {err}");
+                exit(74);
+            }
+        },
+        None => {}
+    }
+    let mut rlox = RLox::new(interpreter);
+    let coverage = match (&options.coverage, &options.input) {
+        (Some(_), Input::File(_)) => rlox.run_with_coverage(source),
+        _ => {
+            rlox.run(source);
+            None
+        }
+    };
+    if let Some(Err(err)) = rlox.take_tracer().map(Tracer::finish) {
+        eprintln!("Could not write trace: {err}");
+        exit(74);
+    }
+    if rlox.had_error {
+        exit(65);
+    }
+
+    if let (Some(lcov_file), Input::File(file_name), Some(coverage)) =
+        (&options.coverage, &options.input, coverage)
+    {
+        let mut lcov = Vec::new();
+        if let Err(err) = coverage.write_lcov(file_name, &mut lcov) {
+            eprintln!("Could not write coverage: {err}");
+            exit(74);
+        }
+        write_output_file(lcov_file, &lcov);
+        eprintln!("Coverage: {}", coverage.summary());
+        if let Some(uncovered) = uncovered_lines(&coverage) {
+            eprintln!("{uncovered}");
+        }
+    }
+    if let (Some(folded_file), Some(profiler)) = (&options.profile, rlox.take_profiler()) {
+        let mut folded = Vec::new();
+        let written = profiler
+            .write_folded(&mut folded)
+            .and_then(|_| profiler.write_report(&mut io::stderr()));
+        if let Err(err) = written {
+            eprintln!("Could not write profile: {err}");
+            exit(74);
+        }
+        write_output_file(folded_file, &folded);
+    }
+
+    if rlox.had_runtime_error {
+        exit(70);
+    }
+}
+
+fn write_output_file(file_name: &str, contents: &[u8]) {
+    if let Err(err) = fs::write(file_name, contents) {
+        eprintln!("Could not write {file_name}: {err}");
+        exit(74);
+    }
+}
+
+/// Lists the lines that never ran as `Not covered: 3-4, 8`, or `None` if every line ran.
+fn uncovered_lines(coverage: &coverage::Coverage) -> Option<String> {
+    let ranges: Vec<String> = coverage
+        .uncovered_lines()
+        .into_iter()
+        .map(|(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{first}-{last}"),
+        })
+        .collect();
+    (!ranges.is_empty()).then(|| format!("Not covered: {}", ranges.join(", ")))
+}
+
+/// Runs the program under the debugger, reading commands from stdin. Quitting from
+/// the debugger is not an error.
+fn debug_source(source: &str) {
+    let quit = Rc::new(Cell::new(false));
+    let prompt = DebugPrompt::new(source, Box::new(io::stdin().lock()), Box::new(io::stdout()))
+        .on_quit(Rc::clone(&quit));
+
+    let environment = Environment::default();
+    let mut stdout = io::stdout();
+    let mut interpreter = Interpreter::new(environment, &mut stdout);
+    interpreter.set_debugger(Debugger::new(Box::new(prompt)));
+    let mut rlox = RLox::new(interpreter);
+    rlox.run(source);
+    if rlox.had_error {
+        exit(65);
+    }
+    if rlox.had_runtime_error && !quit.get() {
+        exit(70);
     }
 }
 
 fn run_prompt() {
-    let mut lines = io::stdin().lock().lines();
     let environment = Environment::default();
     let mut stdout = io::stdout();
     let interpreter = Interpreter::new(environment, &mut stdout);
     let mut rlox = RLox::new(interpreter);
+    let mut buffer = String::new();
+
+    let mut editor = match Editor::<LoxHelper, FileHistory>::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("Could not start line editor: {err}");
+            exit(74);
+        }
+    };
+    editor.set_helper(Some(LoxHelper::default()));
+    let history = repl::history_path();
+    if let Some(history) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(history);
+    }
 
     loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
+        if let Some(helper) = editor.helper_mut() {
+            helper.update_names(&rlox);
+        }
 
-        if let Some(Ok(line)) = lines.next() {
-            if line.is_empty() {
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
                 println!("Exiting...");
                 break;
             }
+            Err(err) => {
+                eprintln!("Error reading line: {err}");
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        if buffer.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.trim_start().starts_with(':') {
+                match repl::run_command(&mut rlox, &line) {
+                    repl::Command::Continue => continue,
+                    repl::Command::Quit => {
+                        println!("Exiting...");
+                        break;
+                    }
+                }
+            }
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+        if !repl::is_ready(&buffer) {
+            continue;
+        }
+
+        rlox.run_repl(&buffer);
+        println!();
+        rlox.had_error = false;
+        rlox.had_runtime_error = false;
+        buffer.clear();
+    }
 
-            rlox.run(&line);
-            println!();
-            rlox.had_error = false;
-        } else {
-            eprintln!("Error reading line or EOF reached");
-            break;
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("Could not save history to {}: {err}", history.display());
         }
     }
 }
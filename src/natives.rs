@@ -0,0 +1,175 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    callable::{Callable, NativeFunction},
+    capabilities::Capability,
+    environment::Environment,
+    error::{ErrorKind, RuntimeError},
+    expr::Literal,
+    interpreter::is_truthy,
+    runtime_err,
+};
+
+/// Defines every native function as a global in the given environment.
+pub fn define_globals(environment: &mut Environment) {
+    let natives = [
+        NativeFunction {
+            name: "clock",
+            arity: 0,
+            capability: Some(Capability::Time),
+            function: clock,
+        },
+        NativeFunction {
+            name: "random",
+            arity: 0,
+            capability: Some(Capability::Random),
+            function: random,
+        },
+        NativeFunction {
+            name: "getenv",
+            arity: 1,
+            capability: Some(Capability::Env),
+            function: getenv,
+        },
+        NativeFunction {
+            name: "read_file",
+            arity: 1,
+            capability: Some(Capability::FileIo),
+            function: read_file,
+        },
+        NativeFunction {
+            name: "write_file",
+            arity: 2,
+            capability: Some(Capability::FileIo),
+            function: write_file,
+        },
+        NativeFunction {
+            name: "read_line",
+            arity: 0,
+            capability: Some(Capability::Stdin),
+            function: read_line,
+        },
+        NativeFunction {
+            name: "assert",
+            arity: 2,
+            capability: None,
+            function: assert,
+        },
+        NativeFunction {
+            name: "assert_eq",
+            arity: 2,
+            capability: None,
+            function: assert_eq,
+        },
+    ];
+
+    for native in natives {
+        environment.define(
+            native.name.to_string(),
+            Literal::Callable(Callable::Native(Rc::new(native))),
+        );
+    }
+}
+
+/// Returns the string argument at `index`, or an error naming the native and parameter.
+fn string_argument<'a>(
+    arguments: &'a [Literal],
+    index: usize,
+    native: &str,
+    parameter: &str,
+) -> Result<&'a str, RuntimeError> {
+    match &arguments[index] {
+        Literal::String(value) => Ok(value),
+        other => Err(runtime_err!(
+            format!("{} expects a string {}, got {}.", native, parameter, other),
+            None
+        )),
+    }
+}
+
+/// Returns the number of seconds since the Unix epoch.
+fn clock(_arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| Literal::Number(duration.as_secs_f64()))
+        .map_err(|err| runtime_err!(format!("Failed to read clock: {}", err), None))
+}
+
+/// Returns a random number in `[0, 1)`.
+fn random(_arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    // Every `RandomState` is seeded with fresh random keys.
+    let bits = RandomState::new().build_hasher().finish();
+    Ok(Literal::Number((bits >> 11) as f64 / (1u64 << 53) as f64))
+}
+
+/// Returns the value of an environment variable, or `nil` if it is not set.
+fn getenv(arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    let name = string_argument(arguments, 0, "getenv", "name")?;
+    Ok(env::var(name).map(Literal::String).unwrap_or(Literal::None))
+}
+
+/// Returns the contents of a file.
+fn read_file(arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    let path = string_argument(arguments, 0, "read_file", "path")?;
+    fs::read_to_string(path)
+        .map(Literal::String)
+        .map_err(|err| runtime_err!(format!("Could not read {}: {}", path, err), None))
+}
+
+/// Replaces the contents of a file.
+fn write_file(arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    let path = string_argument(arguments, 0, "write_file", "path")?;
+    let contents = string_argument(arguments, 1, "write_file", "contents")?;
+    fs::write(path, contents)
+        .map(|_| Literal::None)
+        .map_err(|err| runtime_err!(format!("Could not write {}: {}", path, err), None))
+}
+
+/// Returns the next line of standard input without its line ending, or `nil` at the
+/// end of the input.
+fn read_line(_arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) => Ok(Literal::None),
+        Ok(_) => {
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+            Ok(Literal::String(line))
+        }
+        Err(err) => Err(runtime_err!(
+            format!("Could not read from stdin: {}", err),
+            None
+        )),
+    }
+}
+
+/// Fails with the given message unless the condition is truthy.
+fn assert(arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    if is_truthy(&arguments[0]) {
+        return Ok(Literal::None);
+    }
+    Err(runtime_err!(
+        ErrorKind::AssertionFailed,
+        format!("Assertion failed: {}", arguments[1].printable()),
+        None
+    ))
+}
+
+/// Fails unless the actual value, first, equals the expected value, second.
+fn assert_eq(arguments: &[Literal]) -> Result<Literal, RuntimeError> {
+    let (actual, expected) = (&arguments[0], &arguments[1]);
+    if actual == expected {
+        return Ok(Literal::None);
+    }
+    Err(runtime_err!(
+        ErrorKind::AssertionFailed,
+        format!("Assertion failed: expected {}, got {}.", expected, actual),
+        None
+    ))
+}
@@ -4,20 +4,51 @@ use crate::{
     token::{self, TokenKind},
 };
 use anyhow::{anyhow, Result};
+use std::{
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+/// The maximum number of parameters a function can declare, or arguments a call can pass.
+const MAX_ARGUMENTS: usize = 255;
+
+/// A syntax error and the token it was found at.
+///
+/// The parser returns these wrapped in `anyhow::Error`, so tools that need the error's
+/// position can downcast to it.
+#[derive(Debug)]
+pub struct ParseError {
+    pub token: token::Token,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 /// The `Parser` struct is responsible for parsing a sequence of tokens.
 ///
 /// # Fields
 /// - `tokens`: A vector containing the tokens to be parsed.
 /// - `current`: The index of the current token being processed.
+/// - `function_depth`: How many function bodies enclose the current token.
 pub struct Parser<'a> {
     tokens: &'a Vec<token::Token>,
     current: usize,
+    function_depth: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a Vec<token::Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            function_depth: 0,
+        }
     }
 
     /// Parses an expression and returns the resulting AST node.
@@ -30,9 +61,44 @@ impl<'a> Parser<'a> {
         Ok(statements)
     }
 
-    /// Parses a declaration statement, such as a variable declaration.
-    /// This method attempts to parse a `var` declaration or falls back to a generic statement.
+    /// Parses input consisting of a single expression, optionally followed by a `;`.
+    ///
+    /// Returns `None` without reporting anything when the input starts with a statement
+    /// keyword or holds more than one statement, so that it can be parsed again with
+    /// [`Parser::parse`]. Used by the REPL to echo the values of bare expressions.
+    pub fn parse_expression(&mut self) -> Option<Result<expr::Expr>> {
+        match self.peek().kind {
+            TokenKind::Var
+            | TokenKind::Print
+            | TokenKind::If
+            | TokenKind::While
+            | TokenKind::For
+            | TokenKind::Return
+            | TokenKind::Class
+            | TokenKind::LeftBrace
+            | TokenKind::Eof => return None,
+            TokenKind::Fun if self.check_next(&TokenKind::Identifier) => return None,
+            _ => {}
+        }
+
+        let expr = match self.expression() {
+            Ok(expr) => expr,
+            Err(err) => return Some(Err(err)),
+        };
+        self.match_kinds(&[TokenKind::Semicolon]);
+        self.at_end().then_some(Ok(expr))
+    }
+
+    /// Parses a declaration statement, such as a variable or function declaration.
+    /// This method attempts to parse a `var` or `fun` declaration or falls back to a generic statement.
+    /// A `fun` not followed by a name starts a lambda expression statement instead.
     fn declaration(&mut self) -> Result<stmt::Stmt> {
+        if self.check(&TokenKind::Fun) && self.check_next(&TokenKind::Identifier) {
+            self.advance();
+            return self.function_declaration().inspect_err(|_| {
+                self.synchronize();
+            });
+        }
         match self.match_kinds(&[TokenKind::Var]) {
             true => self.var_declaration().inspect_err(|_| {
                 self.synchronize();
@@ -43,6 +109,56 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Parses a named function declaration. This method assumes the `fun` keyword has already been matched.
+    fn function_declaration(&mut self) -> Result<stmt::Stmt> {
+        let name = self
+            .consume(&TokenKind::Identifier, "Expect function name.")?
+            .clone();
+        self.consume(&TokenKind::LeftParen, "Expect '(' after function name.")?;
+        let params = self.parameters()?;
+        self.consume(&TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.function_body()?;
+
+        Ok(stmt::Stmt::Function {
+            name,
+            params,
+            body: Rc::new(body),
+        })
+    }
+
+    /// Parses a comma-separated parameter list, including the closing ')'.
+    /// This method assumes the opening '(' has already been consumed.
+    fn parameters(&mut self) -> Result<Vec<token::Token>> {
+        let mut params = Vec::new();
+
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    crate::error_token(self.peek(), "Can't have more than 255 parameters.");
+                }
+                params.push(
+                    self.consume(&TokenKind::Identifier, "Expect parameter name.")?
+                        .clone(),
+                );
+                if !self.match_kinds(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(&TokenKind::RightParen, "Expect ')' after parameters.")?;
+        Ok(params)
+    }
+
+    /// Parses the statements of a function body, including the closing '}'.
+    /// This method assumes the opening '{' has already been consumed.
+    fn function_body(&mut self) -> Result<Vec<stmt::Stmt>> {
+        self.function_depth += 1;
+        let body = self.block();
+        self.function_depth -= 1;
+        body
+    }
+
     /// Parses a variable declaration statement.
     /// If an initializer is present, it is parsed; otherwise, the initializer is set to `None`.
     /// This method expects a semicolon after the declaration.
@@ -81,10 +197,32 @@ impl<'a> Parser<'a> {
         if self.match_kinds(&[TokenKind::For]) {
             return self.for_statement();
         }
+        if self.match_kinds(&[TokenKind::Return]) {
+            return self.return_statement();
+        }
         self.expression_statement()
     }
 
+    /// Parses a return statement. This method assumes the `return` keyword has already been matched.
+    /// Returning outside of a function body is an error.
+    fn return_statement(&mut self) -> Result<stmt::Stmt> {
+        let keyword = self.previous().clone();
+        if self.function_depth == 0 {
+            return Err(self.error(&keyword, "Can't return from top-level code."));
+        }
+
+        let value = if !self.check(&TokenKind::Semicolon) {
+            self.expression()?
+        } else {
+            expr::Expr::literal(expr::Literal::None)
+        };
+
+        self.consume(&TokenKind::Semicolon, "Expect ';' after return value.")?;
+        Ok(stmt::Stmt::Return { keyword, value })
+    }
+
     fn if_statement(&mut self) -> Result<stmt::Stmt> {
+        let keyword = self.previous().clone();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(&TokenKind::RightParen, "Expect ')' after if condition.")?;
@@ -97,6 +235,7 @@ impl<'a> Parser<'a> {
         };
 
         Ok(stmt::Stmt::If {
+            keyword,
             condition,
             then_branch,
             else_branch,
@@ -104,6 +243,7 @@ impl<'a> Parser<'a> {
     }
 
     fn for_statement(&mut self) -> Result<stmt::Stmt> {
+        let keyword = self.previous().clone();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'for'.")?;
 
         let initializer = if self.match_kinds(&[TokenKind::Semicolon]) {
@@ -135,6 +275,7 @@ impl<'a> Parser<'a> {
         }
 
         body = stmt::Stmt::While {
+            keyword,
             condition,
             body: Box::new(body),
         };
@@ -147,16 +288,26 @@ impl<'a> Parser<'a> {
     }
 
     fn while_statement(&mut self) -> Result<stmt::Stmt> {
+        let keyword = self.previous().clone();
         self.consume(&TokenKind::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(&TokenKind::RightParen, "Expect ')' after condition.")?;
         let body = Box::new(self.statement()?);
 
-        Ok(stmt::Stmt::While { condition, body })
+        Ok(stmt::Stmt::While {
+            keyword,
+            condition,
+            body,
+        })
     }
 
     /// Parses a block statement, which consists of multiple statements enclosed in braces (`{}`).
     fn block_statement(&mut self) -> Result<stmt::Stmt> {
+        Ok(stmt::Stmt::Block(self.block()?))
+    }
+
+    /// Parses the statements of a block up to and including the closing '}'.
+    fn block(&mut self) -> Result<Vec<stmt::Stmt>> {
         let mut statements = Vec::new();
 
         while !self.check(&TokenKind::RightBrace) && !self.at_end() {
@@ -164,14 +315,18 @@ impl<'a> Parser<'a> {
         }
 
         self.consume(&TokenKind::RightBrace, "Expect '}' after block.")?;
-        Ok(stmt::Stmt::Block(statements))
+        Ok(statements)
     }
 
     /// Parses a print statement. This method assumes the `print` keyword has already been matched.
     fn print_statement(&mut self) -> Result<stmt::Stmt> {
-        let expr = self.expression()?;
+        let keyword = self.previous().clone();
+        let expression = self.expression()?;
         self.consume(&TokenKind::Semicolon, "Expect ';' after expression.")?;
-        Ok(stmt::Stmt::Print(expr))
+        Ok(stmt::Stmt::Print {
+            keyword,
+            expression,
+        })
     }
 
     /// Parses an expression statement. This method expects a semicolon after the expression.
@@ -189,10 +344,11 @@ impl<'a> Parser<'a> {
         self.assignment()
     }
 
-    /// Parses an assignment expression. This includes parsing variable assignments.
+    /// Parses an assignment expression. This includes parsing variable assignments
+    /// and compound assignments (`+=`, `-=`, `*=`, `/=`, `%=`).
     /// If the left-hand side is not a valid assignment target, an error is raised.
     fn assignment(&mut self) -> Result<expr::Expr> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
 
         if self.match_kinds(&[TokenKind::Equal]) {
             let equals = self.previous().clone();
@@ -203,10 +359,101 @@ impl<'a> Parser<'a> {
                     name,
                     value: Box::new(value),
                 }),
-                _ => {
-                    crate::error_token(&equals, "invalid assignment target.");
-                    Err(anyhow!("Invalid assignment target."))
-                }
+                _ => Err(self.error(&equals, "Invalid assignment target.")),
+            };
+        }
+
+        if self.match_kinds(&[
+            TokenKind::PlusEqual,
+            TokenKind::MinusEqual,
+            TokenKind::StarEqual,
+            TokenKind::SlashEqual,
+            TokenKind::PercentEqual,
+        ]) {
+            let operator = self.previous().clone();
+            let value = self.assignment()?;
+            return self.compound_assignment(expr, operator, value);
+        }
+
+        Ok(expr)
+    }
+
+    /// Desugars a compound assignment such as `a += b` into `a = a + b`.
+    ///
+    /// Only variables are valid targets, and reading a variable has no side effects,
+    /// so the target is still effectively evaluated once.
+    fn compound_assignment(
+        &mut self,
+        target: expr::Expr,
+        operator: token::Token,
+        value: expr::Expr,
+    ) -> Result<expr::Expr> {
+        let kind = match operator.kind {
+            TokenKind::PlusEqual => TokenKind::Plus,
+            TokenKind::MinusEqual => TokenKind::Minus,
+            TokenKind::StarEqual => TokenKind::Star,
+            TokenKind::SlashEqual => TokenKind::Slash,
+            TokenKind::PercentEqual => TokenKind::Percent,
+            _ => unreachable!("not a compound assignment operator"),
+        };
+        let binary_operator = token::Token {
+            kind,
+            lexeme: operator.lexeme.trim_end_matches('=').to_string(),
+            literal: token::Literal::None,
+            line: operator.line,
+            column: operator.column,
+            offset: operator.offset,
+        };
+
+        match target {
+            expr::Expr::Variable { name } => Ok(expr::Expr::Assign {
+                name: name.clone(),
+                value: Box::new(expr::Expr::binary(
+                    expr::Expr::Variable { name },
+                    binary_operator,
+                    value,
+                )),
+            }),
+            _ => Err(self.error(&operator, "Invalid assignment target.")),
+        }
+    }
+
+    /// Parses a ternary conditional expression (`cond ? a : b`).
+    ///
+    /// The then-branch may be any expression, including an assignment, while the
+    /// else-branch is parsed at the same level, making the operator right-associative:
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`.
+    fn conditional(&mut self) -> Result<expr::Expr> {
+        let condition = self.coalesce()?;
+
+        if self.match_kinds(&[TokenKind::Question]) {
+            let then_branch = self.expression()?;
+            self.consume(
+                &TokenKind::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            let else_branch = self.conditional()?;
+            return Ok(expr::Expr::Conditional {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            });
+        }
+
+        Ok(condition)
+    }
+
+    /// Parses a nil-coalescing expression (`a ?? b`), which binds looser than `or`.
+    fn coalesce(&mut self) -> Result<expr::Expr> {
+        let mut expr = self.or()?;
+
+        while self.match_kinds(&[TokenKind::QuestionQuestion]) {
+            let operator = self.previous().clone();
+            let right = self.or()?;
+            expr = expr::Expr::Logical {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
             };
         }
 
@@ -297,15 +544,20 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    /// Parses a factor expression, handling multiplication and division.
+    /// Parses a factor expression, handling multiplication, division, modulo and integer division.
     ///
-    /// A factor consists of one or more unary expressions separated by '*' or '/'.
-    /// For example: `a * b / c`
+    /// A factor consists of one or more unary expressions separated by '*', '/', '%' or '~/'.
+    /// For example: `a * b / c % d`
     fn factor(&mut self) -> Result<expr::Expr> {
         let mut expr = self.unary()?;
 
-        // Continue parsing as long as the next token is '*' or '/'.
-        while self.match_kinds(&[TokenKind::Slash, TokenKind::Star]) {
+        // Continue parsing as long as the next token is '*', '/', '%' or '~/'.
+        while self.match_kinds(&[
+            TokenKind::Slash,
+            TokenKind::Star,
+            TokenKind::Percent,
+            TokenKind::TildeSlash,
+        ]) {
             let operator = self.previous().clone();
             let right = self.unary()?;
             expr = expr::Expr::binary(expr, operator, right);
@@ -316,7 +568,7 @@ impl<'a> Parser<'a> {
 
     /// Parses a unary expression, handling negation and logical NOT.
     ///
-    /// A unary expression starts with '-' or '!' followed by another unary or primary expression.
+    /// A unary expression starts with '-' or '!' followed by another unary or exponent expression.
     /// For example: `-a`, `!b`
     fn unary(&mut self) -> Result<expr::Expr> {
         // If the next token is '!' or '-', parse as a unary expression.
@@ -325,14 +577,142 @@ impl<'a> Parser<'a> {
             let right = self.unary()?;
             return Ok(expr::Expr::unary(operator, right));
         }
-        // Otherwise, parse as a primary expression.
-        self.primary()
+        // Otherwise, parse as an exponent expression.
+        self.exponent()
+    }
+
+    /// Parses an exponent expression, handling '**'.
+    ///
+    /// Exponentiation is right-associative and binds tighter than unary operators on its
+    /// left, so `-a ** b` is `-(a ** b)` and `a ** b ** c` is `a ** (b ** c)`. The right
+    /// operand may itself be a unary expression, as in `a ** -b`.
+    fn exponent(&mut self) -> Result<expr::Expr> {
+        let expr = self.call()?;
+
+        if self.match_kinds(&[TokenKind::StarStar]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            return Ok(expr::Expr::binary(expr, operator, right));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a call expression: a primary expression followed by any number of
+    /// parenthesized argument lists, as in `f(1)(2)`.
+    fn call(&mut self) -> Result<expr::Expr> {
+        let mut expr = self.primary()?;
+
+        while self.match_kinds(&[TokenKind::LeftParen]) {
+            expr = self.finish_call(expr)?;
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses the arguments of a call. This method assumes the '(' has already been matched.
+    fn finish_call(&mut self, callee: expr::Expr) -> Result<expr::Expr> {
+        let mut arguments = Vec::new();
+
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    crate::error_token(self.peek(), "Can't have more than 255 arguments.");
+                }
+                arguments.push(self.expression()?);
+                if !self.match_kinds(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self
+            .consume(&TokenKind::RightParen, "Expect ')' after arguments.")?
+            .clone();
+
+        Ok(expr::Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    /// Parses a lambda expression such as `fun (a, b) { ... }`.
+    /// This method assumes the `fun` keyword has already been matched.
+    fn lambda(&mut self) -> Result<expr::Expr> {
+        let keyword = self.previous().clone();
+        self.consume(&TokenKind::LeftParen, "Expect '(' after 'fun'.")?;
+        let params = self.parameters()?;
+        self.consume(&TokenKind::LeftBrace, "Expect '{' before lambda body.")?;
+        let body = self.function_body()?;
+
+        Ok(expr::Expr::Lambda {
+            keyword,
+            params,
+            body: Rc::new(body),
+        })
+    }
+
+    /// Parses an arrow lambda such as `(a, b) => a + b` or `(a, b) => { ... }`.
+    /// An expression body is treated as a function body returning that expression.
+    fn arrow_lambda(&mut self) -> Result<expr::Expr> {
+        self.consume(
+            &TokenKind::LeftParen,
+            "Expect '(' before lambda parameters.",
+        )?;
+        let params = self.parameters()?;
+        let keyword = self
+            .consume(&TokenKind::Arrow, "Expect '=>' after lambda parameters.")?
+            .clone();
+
+        let body = if self.match_kinds(&[TokenKind::LeftBrace]) {
+            self.function_body()?
+        } else {
+            let value = self.expression()?;
+            vec![stmt::Stmt::Return {
+                keyword: keyword.clone(),
+                value,
+            }]
+        };
+
+        Ok(expr::Expr::Lambda {
+            keyword,
+            params,
+            body: Rc::new(body),
+        })
+    }
+
+    /// Looks ahead from the current '(' to decide whether it starts an arrow lambda,
+    /// i.e. a possibly empty list of comma-separated identifiers followed by `) =>`.
+    fn is_arrow_lambda(&self) -> bool {
+        let kind = |offset: usize| {
+            self.tokens
+                .get(self.current + offset)
+                .map_or(TokenKind::Eof, |token| token.kind)
+        };
+
+        let mut offset = 1;
+        if kind(offset) != TokenKind::RightParen {
+            loop {
+                if kind(offset) != TokenKind::Identifier {
+                    return false;
+                }
+                offset += 1;
+                match kind(offset) {
+                    TokenKind::Comma => offset += 1,
+                    TokenKind::RightParen => break,
+                    _ => return false,
+                }
+            }
+        }
+
+        kind(offset + 1) == TokenKind::Arrow
     }
 
     /// Parses a primary expression from the token stream.
     ///
     /// This method handles literals (`true`, `false`, `nil`, numbers, strings),
-    /// and parenthesized expressions. If the next token matches one of these,
+    /// lambdas, and parenthesized expressions. If the next token matches one of these,
     /// it consumes the token and returns the corresponding expression node.
     ///
     /// # Returns
@@ -358,10 +738,15 @@ impl<'a> Parser<'a> {
                 _ => panic!(),
             }
         }
+        if self.match_kinds(&[TokenKind::Fun]) {
+            return self.lambda();
+        }
+        if self.check(&TokenKind::LeftParen) && self.is_arrow_lambda() {
+            return self.arrow_lambda();
+        }
         if self.match_kinds(&[TokenKind::LeftParen]) {
             let expr = self.expression()?;
-            self.consume(&TokenKind::RightParen, "Expect ')' after expression.")
-                .unwrap();
+            self.consume(&TokenKind::RightParen, "Expect ')' after expression.")?;
             return Ok(expr::Expr::grouping(expr));
         }
 
@@ -370,7 +755,7 @@ impl<'a> Parser<'a> {
             return Ok(expr::Expr::Variable { name });
         }
 
-        Err(anyhow!("Expect expression."))
+        Err(self.error(self.peek(), "Expect expression."))
     }
 
     /// Attempts to match any of the provided `TokenKind`s against the current token.
@@ -397,8 +782,23 @@ impl<'a> Parser<'a> {
         if self.check(kind) {
             return Ok(self.advance());
         }
-        crate::error_token(self.peek(), message);
-        Err(anyhow!("{}", message))
+        Err(self.error(self.peek(), message))
+    }
+
+    /// Reports a syntax error at `token` and returns it as a [`ParseError`].
+    fn error(&self, token: &token::Token, message: &str) -> anyhow::Error {
+        crate::error_token(token, message);
+        anyhow!(ParseError {
+            token: token.clone(),
+            message: message.to_string(),
+        })
+    }
+
+    /// Checks if the token after the current one matches the given `TokenKind`.
+    fn check_next(&self, kind: &TokenKind) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.kind == *kind)
     }
 
     /// Checks if the current token matches the given `TokenKind`.
@@ -484,6 +884,7 @@ impl<'a> Parser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scanner::Scanner;
     use crate::token::{self};
     use crate::utils::tests::{test_case::TestCase, token_eof};
 
@@ -497,18 +898,24 @@ mod tests {
                         lexeme: "-".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Number,
                         lexeme: "456".to_string(),
                         literal: 456f64.into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Semicolon,
                         lexeme: ";".to_string(),
                         literal: ";".into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token_eof(1),
                 ],
@@ -521,36 +928,48 @@ mod tests {
                         lexeme: "(".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Number,
                         lexeme: "456".to_string(),
                         literal: 456f64.into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Star,
                         lexeme: "*".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Number,
                         lexeme: "789".to_string(),
                         literal: 789f64.into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::RightParen,
                         lexeme: ")".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Semicolon,
                         lexeme: ";".to_string(),
                         literal: ";".into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token_eof(1),
                 ],
@@ -563,12 +982,16 @@ mod tests {
                         lexeme: "\"hello\"".to_string(),
                         literal: "hello".into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Semicolon,
                         lexeme: ";".to_string(),
                         literal: ";".into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token_eof(1),
                 ],
@@ -581,42 +1004,56 @@ mod tests {
                         lexeme: "-".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Number,
                         lexeme: "123".to_string(),
                         literal: 123f64.into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Star,
                         lexeme: "*".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::LeftParen,
                         lexeme: "(".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Number,
                         lexeme: "45.67".to_string(),
                         literal: 45.67f64.into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::RightParen,
                         lexeme: ")".to_string(),
                         literal: token::Literal::None,
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token::Token {
                         kind: token::TokenKind::Semicolon,
                         lexeme: ";".to_string(),
                         literal: ";".into(),
                         line: 1,
+                        column: 0,
+                        offset: 0,
                     },
                     token_eof(1),
                 ],
@@ -637,4 +1074,112 @@ mod tests {
             };
         }
     }
+
+    #[test]
+    fn test_arithmetic_precedence() {
+        let test_cases = vec![
+            TestCase {
+                input: "-2 ** 2;",
+                expected: "(- (** 2 2))",
+            },
+            TestCase {
+                input: "2 ** 3 ** 2;",
+                expected: "(** 2 (** 3 2))",
+            },
+            TestCase {
+                input: "2 ** -1;",
+                expected: "(** 2 (- 1))",
+            },
+            TestCase {
+                input: "1 + 7 % 3 * 2 ** 2;",
+                expected: "(+ 1 (* (% 7 3) (** 2 2)))",
+            },
+            TestCase {
+                input: "7 ~/ 2 - 1;",
+                expected: "(- (~/ 7 2) 1)",
+            },
+            TestCase {
+                input: "a ? b : c ? d : e;",
+                expected: "(?: a b (?: c d e))",
+            },
+            TestCase {
+                input: "x = a or b ?? c ? 1 : 2;",
+                expected: "(= x (?: (?? (or a b) c) 1 2))",
+            },
+            TestCase {
+                input: "a ?? b ?? c;",
+                expected: "(?? (?? a b) c)",
+            },
+            TestCase {
+                input: "i += 2 * 3;",
+                expected: "(= i (+ i (* 2 3)))",
+            },
+            TestCase {
+                input: "i %= j -= 1;",
+                expected: "(= i (% i (= j (- j 1))))",
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut scanner = Scanner::new(test_case.input);
+            let tokens = scanner.scan_tokens();
+            let mut parser = Parser::new(tokens);
+            let stmts = parser.parse().expect("parsing failed");
+            assert_eq!(stmts.len(), 1);
+            assert_eq!(
+                stmts[0].to_string(),
+                test_case.expected,
+                "Failed on input: {}",
+                test_case.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_function_parsing() {
+        let test_cases = vec![
+            TestCase {
+                input: "f(1)(2, x);",
+                expected: "(call (call f 1) 2 x)",
+            },
+            TestCase {
+                input: "fun add(a, b) { return a + b; }",
+                expected: "fun add(a b) { return (+ a b) }",
+            },
+            TestCase {
+                input: "var f = (x) => x * 2;",
+                expected: "var f = (fun (x) { return (* x 2) })",
+            },
+            TestCase {
+                input: "var f = fun () { print 1; };",
+                expected: "var f = (fun () { print 1 })",
+            },
+            TestCase {
+                input: "(x) * 2;",
+                expected: "(* (group x) 2)",
+            },
+        ];
+
+        for test_case in test_cases {
+            let mut scanner = Scanner::new(test_case.input);
+            let tokens = scanner.scan_tokens();
+            let mut parser = Parser::new(tokens);
+            let stmts = parser.parse().expect("parsing failed");
+            assert_eq!(stmts.len(), 1);
+            assert_eq!(
+                stmts[0].to_string(),
+                test_case.expected,
+                "Failed on input: {}",
+                test_case.input
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_level_return() {
+        let mut scanner = Scanner::new("return 1;");
+        let tokens = scanner.scan_tokens();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.parse().is_err());
+    }
 }
@@ -0,0 +1,319 @@
+//! An instrumenting profiler, counting the calls and time of every function and the
+//! executions and time of every source line.
+//!
+//! Time is attributed as the program runs: everything between two events, a statement
+//! starting or a function being entered or left, is charged to the line that was
+//! running and to the call stack it ran in. A line's time is therefore its self time,
+//! excluding the statements it runs in function bodies and nested blocks.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use crate::stmt::Stmt;
+
+/// The name of the outermost frame, the top-level code of the program.
+pub const ROOT_FRAME: &str = "<script>";
+
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    start: Instant,
+    /// The line that made the call, running again when the call returns.
+    caller_line: Option<usize>,
+    /// The length of the folded stack before this frame was pushed.
+    folded_len: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct FunctionStats {
+    calls: u64,
+    /// The time from entering to leaving the function, counted once for recursive calls.
+    total: Duration,
+    /// The time spent in the function itself, excluding the functions it calls.
+    self_time: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct LineStats {
+    hits: u64,
+    time: Duration,
+}
+
+#[derive(Debug)]
+pub struct Profiler {
+    /// Calls in progress, innermost last.
+    stack: Vec<Frame>,
+    /// The frame names of the stack joined by `;`, starting with [`ROOT_FRAME`].
+    folded: String,
+    functions: HashMap<String, FunctionStats>,
+    lines: BTreeMap<usize, LineStats>,
+    /// Self time by folded stack.
+    stacks: HashMap<String, Duration>,
+    /// The line running since the last event.
+    line: Option<usize>,
+    /// When the last event happened, or `None` while no program runs.
+    last: Option<Instant>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler {
+            stack: Vec::new(),
+            folded: ROOT_FRAME.to_string(),
+            functions: HashMap::new(),
+            lines: BTreeMap::new(),
+            stacks: HashMap::new(),
+            line: None,
+            last: None,
+        }
+    }
+}
+
+impl Profiler {
+    /// Charges the time since the last event to the running line and call stack, and
+    /// returns the current time.
+    fn tick(&mut self) -> Instant {
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let elapsed = now - last;
+            if let Some(line) = self.line {
+                self.lines.entry(line).or_default().time += elapsed;
+            }
+            if let Some(frame) = self.stack.last() {
+                self.functions
+                    .entry(frame.name.clone())
+                    .or_default()
+                    .self_time += elapsed;
+            }
+            match self.stacks.get_mut(&self.folded) {
+                Some(time) => *time += elapsed,
+                None => {
+                    self.stacks.insert(self.folded.clone(), elapsed);
+                }
+            }
+        }
+        self.last = Some(now);
+        now
+    }
+
+    /// Counts a statement about to run.
+    pub(crate) fn record_stmt(&mut self, stmt: &Stmt) {
+        // A block only runs the statements in it, which are counted themselves.
+        if matches!(stmt, Stmt::Block(_)) {
+            return;
+        }
+        self.tick();
+        self.line = stmt.line();
+        if let Some(line) = self.line {
+            self.lines.entry(line).or_default().hits += 1;
+        }
+    }
+
+    /// Counts a call to the function named `name`, which runs until [`Profiler::exit`].
+    pub(crate) fn enter(&mut self, name: &str) {
+        let start = self.tick();
+        self.functions.entry(name.to_string()).or_default().calls += 1;
+        let folded_len = self.folded.len();
+        self.folded.push(';');
+        self.folded.push_str(name);
+        self.stack.push(Frame {
+            name: name.to_string(),
+            start,
+            caller_line: self.line,
+            folded_len,
+        });
+    }
+
+    /// Returns from the innermost call.
+    pub(crate) fn exit(&mut self) {
+        let now = self.tick();
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        // The outermost of recursive calls already counts the time of the inner ones.
+        if !self.stack.iter().any(|outer| outer.name == frame.name) {
+            self.functions.entry(frame.name).or_default().total += now - frame.start;
+        }
+        self.line = frame.caller_line;
+        self.folded.truncate(frame.folded_len);
+    }
+
+    /// Charges the time of the statement that ran last, at the end of a program, and
+    /// stops counting time until the next one starts.
+    pub(crate) fn pause(&mut self) {
+        self.tick();
+        self.last = None;
+        self.line = None;
+    }
+
+    /// The time spent running programs.
+    pub fn total_time(&self) -> Duration {
+        self.stacks.values().sum()
+    }
+
+    /// Writes a report of the functions sorted by total time and of the lines sorted
+    /// by time, slowest first.
+    pub fn write_report(&self, output: &mut impl Write) -> io::Result<()> {
+        let statements: u64 = self.lines.values().map(|line| line.hits).sum();
+        writeln!(
+            output,
+            "Total: {} in {} statements",
+            format_duration(self.total_time()),
+            statements
+        )?;
+
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| {
+            b.total.cmp(&a.total).then_with(|| a_name.cmp(b_name))
+        });
+        writeln!(output, "\nFunctions by total time:")?;
+        writeln!(
+            output,
+            "{:>10} {:>12} {:>12}  function",
+            "calls", "total", "self"
+        )?;
+        for (name, stats) in functions {
+            writeln!(
+                output,
+                "{:>10} {:>12} {:>12}  {}",
+                stats.calls,
+                format_duration(stats.total),
+                format_duration(stats.self_time),
+                name
+            )?;
+        }
+
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by(|(a_line, a), (b_line, b)| b.time.cmp(&a.time).then(a_line.cmp(b_line)));
+        writeln!(output, "\nLines by time:")?;
+        writeln!(output, "{:>10} {:>12}  line", "hits", "time")?;
+        for (line, stats) in lines {
+            writeln!(
+                output,
+                "{:>10} {:>12}  {}",
+                stats.hits,
+                format_duration(stats.time),
+                line
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the time of every call stack in the folded format read by flame graph
+    /// tools: the frames from the outermost, separated by `;`, then the self time of
+    /// the stack in microseconds. Stacks are sorted by name.
+    pub fn write_folded(&self, output: &mut impl Write) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.stacks.iter().collect();
+        stacks.sort();
+        for (stack, time) in stacks {
+            let micros = time.as_micros();
+            if micros > 0 {
+                writeln!(output, "{} {}", stack, micros)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::Environment, interpreter::Interpreter, parser::Parser, scanner::Scanner,
+    };
+
+    fn profile(source: &str) -> Profiler {
+        let mut scanner = Scanner::new(source);
+        let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+        interpreter.set_profiler(Profiler::default());
+        interpreter.interpret(&stmts).unwrap();
+        interpreter.take_profiler().unwrap()
+    }
+
+    const SOURCE: &str = "fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+var twice = (x) => x * 2;
+print twice(fib(10));
+print clock() > 0;
+";
+
+    #[test]
+    fn test_counts() {
+        let profiler = profile(SOURCE);
+        let calls = |name: &str| profiler.functions.get(name).map(|stats| stats.calls);
+        assert_eq!(calls("fib"), Some(177));
+        assert_eq!(calls("<lambda:5>"), Some(1));
+        assert_eq!(calls("clock"), Some(1));
+
+        let hits = |line: usize| profiler.lines.get(&line).map(|stats| stats.hits);
+        // The `if` and the `return` in it share the second line.
+        let test_cases = vec![(1, Some(1)), (2, Some(266)), (3, Some(88)), (4, None)];
+        for (line, expected) in test_cases {
+            assert_eq!(hits(line), expected, "line {}", line);
+        }
+
+        let fib = profiler.functions["fib"];
+        assert!(fib.self_time <= fib.total);
+        assert!(fib.total <= profiler.total_time());
+        assert!(profiler.stack.is_empty());
+        assert_eq!(profiler.folded, ROOT_FRAME);
+    }
+
+    #[test]
+    fn test_stacks() {
+        let profiler = profile(SOURCE);
+        let mut stacks: Vec<&str> = profiler.stacks.keys().map(String::as_str).collect();
+        stacks.sort_unstable();
+        assert_eq!(
+            &stacks[..3],
+            ["<script>", "<script>;<lambda:5>", "<script>;clock"]
+        );
+        assert!(stacks.contains(&"<script>;fib;fib;fib;fib;fib;fib;fib;fib;fib;fib"));
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        for line in String::from_utf8(folded).unwrap().lines() {
+            let (stack, micros) = line.rsplit_once(' ').unwrap();
+            assert!(stack.starts_with(ROOT_FRAME), "{}", line);
+            assert!(micros.parse::<u64>().unwrap() > 0, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let profiler = profile(SOURCE);
+        let mut report = Vec::new();
+        profiler.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        assert!(report.starts_with("Total: "), "{}", report);
+        assert!(report.contains(" in 359 statements\n"), "{}", report);
+        let sections: Vec<&str> = report.split("\n\n").collect();
+        assert_eq!(sections.len(), 3, "{}", report);
+
+        // Functions come slowest first: fib, which the lambda's argument calls, runs
+        // longer than the lambda and the native.
+        let functions: Vec<Vec<&str>> = sections[1]
+            .lines()
+            .skip(2)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(functions[0][0], "177");
+        assert_eq!(functions[0][3], "fib");
+        assert_eq!(functions.len(), 3);
+
+        let lines: Vec<&str> = sections[2].lines().skip(2).collect();
+        assert_eq!(lines.len(), 6, "{}", report);
+    }
+}
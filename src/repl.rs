@@ -0,0 +1,550 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+
+use crate::{
+    parser::Parser,
+    scanner::Scanner,
+    token::{keywords, KEYWORD_MAP},
+    RLox,
+};
+
+/// What the REPL loop should do after a meta-command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Keep reading input.
+    Continue,
+    /// Leave the REPL.
+    Quit,
+}
+
+/// The meta-commands understood by [`run_command`].
+const COMMANDS: [&str; 8] = [
+    ":help", ":vars", ":reset", ":load", ":ast", ":tokens", ":time", ":quit",
+];
+
+const HELP: &str = "\
+Commands:
+  :help          Show this help.
+  :vars          List global variables and their values, except natives.
+  :reset         Discard all variables and functions.
+  :load <file>   Run a script in the current session.
+  :ast <code>    Print the parsed syntax tree of some code.
+  :tokens <code> Print the tokens scanned from some code.
+  :time <code>   Run some code and print how long it took.
+  :quit          Leave the REPL.";
+
+/// Runs a REPL meta-command, a line starting with `:`.
+///
+/// Command output is written to the interpreter's output, like the results of `print`.
+pub fn run_command<W: Write>(rlox: &mut RLox<W>, input: &str) -> Command {
+    let input = input.trim();
+    let (command, argument) = input
+        .split_once(char::is_whitespace)
+        .map_or((input, ""), |(command, argument)| {
+            (command, argument.trim())
+        });
+
+    let result = match command {
+        ":help" => writeln!(rlox.interpreter.output, "{}", HELP),
+        ":quit" => return Command::Quit,
+        ":vars" => rlox
+            .interpreter
+            .environment
+            .globals()
+            .iter()
+            .filter(|(_, value)| !value.is_native())
+            .try_for_each(|(name, value)| {
+                writeln!(rlox.interpreter.output, "{} = {}", name, value)
+            }),
+        ":reset" => {
+            rlox.interpreter.reset();
+            writeln!(rlox.interpreter.output, "Environment reset.")
+        }
+        ":load" => {
+            match fs::read_to_string(argument) {
+                Ok(content) => rlox.run(&content),
+                Err(err) => eprintln!("Could not open: {}: {}", argument, err),
+            }
+            Ok(())
+        }
+        ":ast" => {
+            let mut scanner = Scanner::new(argument);
+            let tokens = scanner.scan_tokens();
+            let output = &mut rlox.interpreter.output;
+            match Parser::new(tokens).parse_expression() {
+                Some(Ok(expr)) => writeln!(output, "{}", expr),
+                Some(Err(err)) => {
+                    eprintln!("{}", err);
+                    Ok(())
+                }
+                None => crate::dump_ast(argument, output).map(|_| ()),
+            }
+        }
+        ":tokens" => crate::dump_tokens(argument, &mut rlox.interpreter.output),
+        ":time" => {
+            let start = Instant::now();
+            rlox.run_repl(argument);
+            writeln!(rlox.interpreter.output, "Elapsed: {:?}", start.elapsed())
+        }
+        _ => {
+            eprintln!(
+                "Unknown command '{}'. Type :help for a list of commands.",
+                command
+            );
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("failed to write output for command {}: {}", command, err);
+    }
+    Command::Continue
+}
+
+/// Returns the path of the file REPL history is kept in, `.rlox_history` in the
+/// user's home directory, or `None` if the home directory is unknown.
+pub fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".rlox_history"))
+}
+
+/// Line editor helper that tab-completes keywords, meta-commands, and the names of
+/// the session's global variables.
+#[derive(Default)]
+pub struct LoxHelper {
+    names: Vec<String>,
+}
+
+impl LoxHelper {
+    /// Refreshes the variable names offered as completions from the session's globals.
+    pub fn update_names<W: Write>(&mut self, rlox: &RLox<W>) {
+        self.names = rlox
+            .interpreter
+            .environment
+            .globals()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let prefix = &line[start..pos];
+
+        // A leading ':' starts a meta-command, which is completed on its own.
+        if start == 1 && line.starts_with(':') {
+            let candidates = COMMANDS
+                .iter()
+                .filter(|command| command[1..].starts_with(prefix))
+                .map(|command| command.to_string())
+                .collect();
+            return Ok((0, candidates));
+        }
+
+        if prefix.is_empty() {
+            return Ok((start, Vec::new()));
+        }
+
+        let mut candidates: Vec<String> = KEYWORD_MAP
+            .keys()
+            .copied()
+            .chain(self.names.iter().map(String::as_str))
+            .filter(|candidate| candidate.starts_with(prefix))
+            .map(str::to_string)
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+/// Keywords that start a statement which must end with `;` or a block.
+const STATEMENT_KEYWORDS: [&str; 7] = [
+    keywords::CLASS,
+    keywords::FOR,
+    keywords::IF,
+    keywords::PRINT,
+    keywords::RETURN,
+    keywords::VAR,
+    keywords::WHILE,
+];
+
+/// Reports whether REPL input is an unfinished statement that needs more lines.
+///
+/// Input is incomplete when it has an unterminated string, more opening than closing
+/// parentheses or braces, ends with an operator, or is a statement such as `print` or
+/// `var` that does not end with a `;` or `}`. A bare expression without `;` is complete,
+/// since the REPL evaluates and echoes it. Comments and blank input are ignored. Input
+/// with too many closing brackets is treated as complete so that the parser can report
+/// the error.
+pub fn is_incomplete(source: &str) -> bool {
+    let scan = scan(source);
+    match scan.depth {
+        _ if scan.open_string => true,
+        depth if depth > 0 => true,
+        depth if depth < 0 => false,
+        _ => match scan.last {
+            None | Some(';') | Some('}') => false,
+            Some('+' | '-' | '*' | '/' | '%' | '=' | '<' | '>' | '!' | '?' | ':' | ',') => true,
+            Some(_) => STATEMENT_KEYWORDS.contains(&scan.first_word.as_str()),
+        },
+    }
+}
+
+/// Reports whether REPL input, ending with the line just read, is ready to run.
+///
+/// Input is ready when it is complete. A blank line also runs it when no bracket or
+/// string is left open, so that a statement that can never be completed still gets
+/// reported, while a blank line inside a function body being typed or pasted does
+/// not. Two blank lines in a row run the input whatever is open.
+pub fn is_ready(source: &str) -> bool {
+    let mut lines = source.lines().rev().map(|line| line.trim().is_empty());
+    match (lines.next(), lines.next()) {
+        (Some(true), Some(true)) => true,
+        (Some(true), _) => {
+            let scan = scan(source);
+            !scan.open_string && scan.depth <= 0
+        }
+        _ => !is_incomplete(source),
+    }
+}
+
+/// What [`is_incomplete`] needs to know about some input.
+struct Scan {
+    /// Opening minus closing parentheses and braces.
+    depth: i64,
+    /// Whether a string is left unterminated.
+    open_string: bool,
+    /// The first word, or the first character if it is not part of a word.
+    first_word: String,
+    /// The last character that is not whitespace or in a comment.
+    last: Option<char>,
+}
+
+fn scan(source: &str) -> Scan {
+    let mut depth: i64 = 0;
+    let mut first_word = String::new();
+    let mut last = None;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if !chars.by_ref().any(|c| c == '"') => {
+                return Scan {
+                    depth,
+                    open_string: true,
+                    first_word,
+                    last,
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+                continue;
+            }
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            c if c.is_whitespace() => continue,
+            _ => {}
+        }
+        if last.is_none() {
+            first_word.push(c);
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                first_word.push(c);
+            }
+            last = first_word.chars().last();
+            continue;
+        }
+        last = Some(c);
+    }
+
+    Scan {
+        depth,
+        open_string: false,
+        first_word,
+        last,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::test_case::TestCase;
+    use crate::{environment::Environment, interpreter::Interpreter};
+
+    #[test]
+    fn test_is_incomplete() {
+        let test_cases = vec![
+            TestCase {
+                input: "print 1;",
+                expected: false,
+            },
+            TestCase {
+                input: "",
+                expected: false,
+            },
+            TestCase {
+                input: "// just a comment",
+                expected: false,
+            },
+            TestCase {
+                input: "print 1",
+                expected: true,
+            },
+            TestCase {
+                input: "var x = 1",
+                expected: true,
+            },
+            TestCase {
+                input: "1 + 2",
+                expected: false,
+            },
+            TestCase {
+                input: "x",
+                expected: false,
+            },
+            TestCase {
+                input: "1 +",
+                expected: true,
+            },
+            TestCase {
+                input: "printer(1)",
+                expected: false,
+            },
+            TestCase {
+                input: "while (i < 10) {",
+                expected: true,
+            },
+            TestCase {
+                input: "while (i < 10) {\n  i = i + 1;\n}",
+                expected: false,
+            },
+            TestCase {
+                input: "print (1 +",
+                expected: true,
+            },
+            TestCase {
+                input: "print \"a multi\nline",
+                expected: true,
+            },
+            TestCase {
+                input: "print \"a } string;\";",
+                expected: false,
+            },
+            TestCase {
+                input: "print 1; // trailing {",
+                expected: false,
+            },
+            TestCase {
+                input: "}",
+                expected: false,
+            },
+        ];
+
+        for case in test_cases {
+            assert_eq!(
+                is_incomplete(case.input),
+                case.expected,
+                "Failed on input: {:?}",
+                case.input
+            );
+        }
+    }
+
+    /// Feeds `lines` to the REPL one at a time, as the prompt does, and returns the
+    /// inputs it runs.
+    fn submissions(lines: &[&str]) -> Vec<String> {
+        let mut buffer = String::new();
+        let mut submitted = Vec::new();
+        for line in lines {
+            if buffer.is_empty() && line.trim().is_empty() {
+                continue;
+            }
+            buffer.push_str(line);
+            buffer.push('\n');
+            if is_ready(&buffer) {
+                submitted.push(std::mem::take(&mut buffer));
+            }
+        }
+        submitted
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let test_cases = vec![
+            TestCase {
+                input: vec![
+                    "fun f() {",
+                    "  var a = 1;",
+                    "",
+                    "  return a;",
+                    "}",
+                    "print f();",
+                ],
+                expected: vec![
+                    "fun f() {\n  var a = 1;\n\n  return a;\n}\n",
+                    "print f();\n",
+                ],
+            },
+            TestCase {
+                input: vec!["print \"a", "", "b\";"],
+                expected: vec!["print \"a\n\nb\";\n"],
+            },
+            TestCase {
+                input: vec!["print 1", "", "print 2;"],
+                expected: vec!["print 1\n\n", "print 2;\n"],
+            },
+            TestCase {
+                input: vec!["fun f() {", "", "", "print 2;"],
+                expected: vec!["fun f() {\n\n\n", "print 2;\n"],
+            },
+        ];
+
+        for case in test_cases {
+            assert_eq!(submissions(&case.input), case.expected, "{:?}", case.input);
+        }
+    }
+
+    fn command_output(setup: &str, commands: &[&str]) -> String {
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(Environment::default(), &mut output);
+        let mut rlox = RLox::new(interpreter);
+        rlox.run(setup);
+        for command in commands {
+            assert_eq!(run_command(&mut rlox, command), Command::Continue);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_commands() {
+        let test_cases = vec![
+            TestCase {
+                input: ("var b = 2; var a = \"x\";", vec![":vars"]),
+                expected: "a = \"x\"\nb = 2\n",
+            },
+            TestCase {
+                input: ("var a = 1;", vec![":reset", ":vars"]),
+                expected: "Environment reset.\n",
+            },
+            TestCase {
+                input: ("", vec![":ast 1 + 2 * 3"]),
+                expected: "(+ 1 (* 2 3))\n",
+            },
+            TestCase {
+                input: ("", vec![":ast var x = -1; print x;"]),
+                expected: "var x = (- 1)\nprint x\n",
+            },
+            TestCase {
+                input: ("", vec![":tokens x = 1"]),
+                expected: "Identifier 'x' String(\"x\") [line 1]\n\
+                           Equal '=' String(\"=\") [line 1]\n\
+                           Number '1' Number(1.0) [line 1]\n\
+                           Eof '' None [line 1]\n",
+            },
+        ];
+
+        for case in test_cases {
+            let (setup, commands) = case.input;
+            assert_eq!(command_output(setup, &commands), case.expected);
+        }
+    }
+
+    #[test]
+    fn test_time_and_load_commands() {
+        let output = command_output("", &[":time 1 + 1"]);
+        assert!(output.starts_with("2\nElapsed: "), "{}", output);
+
+        let path = std::env::temp_dir().join("rlox_repl_load_test.lox");
+        fs::write(&path, "var loaded = 40 + 2;").unwrap();
+        let load = format!(":load {}", path.display());
+        let output = command_output("", &[&load, ":vars"]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(output, "loaded = 42\n");
+    }
+
+    #[test]
+    fn test_quit_command() {
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(Environment::default(), &mut output);
+        let mut rlox = RLox::new(interpreter);
+        assert_eq!(run_command(&mut rlox, ":quit"), Command::Quit);
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(Environment::default(), &mut output);
+        let mut rlox = RLox::new(interpreter);
+        rlox.run("var whale = 1; var count = 2;");
+
+        let mut helper = LoxHelper::default();
+        helper.update_names(&rlox);
+        let history = rustyline::history::DefaultHistory::new();
+        let ctx = Context::new(&history);
+
+        let test_cases = vec![
+            TestCase {
+                input: "wh",
+                expected: (0, vec!["whale", "while"]),
+            },
+            TestCase {
+                input: "print co",
+                expected: (6, vec!["count"]),
+            },
+            TestCase {
+                input: "x + cl",
+                expected: (4, vec!["class", "clock"]),
+            },
+            TestCase {
+                input: "1 + ",
+                expected: (4, vec![]),
+            },
+            TestCase {
+                input: ":t",
+                expected: (0, vec![":tokens", ":time"]),
+            },
+        ];
+
+        for case in test_cases {
+            let (start, candidates) = helper.complete(case.input, case.input.len(), &ctx).unwrap();
+            assert_eq!(
+                (start, candidates),
+                (
+                    case.expected.0,
+                    case.expected.1.iter().map(|c| c.to_string()).collect()
+                ),
+                "Failed on input: {}",
+                case.input
+            );
+        }
+    }
+}
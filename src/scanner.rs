@@ -1,12 +1,46 @@
+use std::fmt::{self, Display, Formatter};
+
 use crate::token::{Literal, Token, TokenKind, KEYWORD_MAP};
 
+/// A `//` comment, which the scanner skips but records for tools such as the formatter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    /// The comment text, including the leading `//` but not the line break.
+    pub text: String,
+    pub line: usize,
+    /// The index of the first token after the comment.
+    pub next_token: usize,
+    /// Whether code precedes the comment on its line.
+    pub trailing: bool,
+}
+
+/// An error found while scanning, such as an unexpected character.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanError {
+    pub message: String,
+    pub line: usize,
+    /// The byte offset in the source where the offending lexeme starts.
+    pub offset: usize,
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}", self.message, self.line)
+    }
+}
+
 pub struct Scanner<'a> {
     source: &'a str,
     tokens: Vec<Token>,
+    comments: Vec<Comment>,
     start: usize,
     current: usize,
     line: usize,
-    errors: Vec<String>,
+    /// The byte offset where the current line starts.
+    line_start: usize,
+    /// The column of the lexeme being scanned.
+    column: usize,
+    errors: Vec<ScanError>,
 }
 
 impl<'a> Scanner<'a> {
@@ -14,9 +48,12 @@ impl<'a> Scanner<'a> {
         Scanner {
             source,
             tokens: Vec::new(),
+            comments: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            column: 1,
             errors: Vec::new(),
         }
     }
@@ -24,6 +61,7 @@ impl<'a> Scanner<'a> {
     pub fn scan_tokens(&mut self) -> &Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.column = self.source[self.line_start..self.start].chars().count() + 1;
             self.scan_token();
         }
         self.tokens.push(Token {
@@ -31,10 +69,22 @@ impl<'a> Scanner<'a> {
             lexeme: "".to_string(),
             literal: Literal::None,
             line: self.line,
+            column: self.source[self.line_start..].chars().count() + 1,
+            offset: self.current,
         });
         &self.tokens
     }
 
+    /// The comments seen so far, in source order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// The scanning errors seen so far.
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
     fn scan_token(&mut self) {
         let c = self.advance();
 
@@ -60,22 +110,73 @@ impl<'a> Scanner<'a> {
                 self.source[self.start..self.current].into(),
             ),
             '.' => self.add_token(TokenKind::Dot, self.source[self.start..self.current].into()),
-            '-' => self.add_token(
-                TokenKind::Minus,
-                self.source[self.start..self.current].into(),
-            ),
-            '+' => self.add_token(
-                TokenKind::Plus,
-                self.source[self.start..self.current].into(),
-            ),
+            '-' => {
+                let token_kind = if self.char_match('=') {
+                    self.advance();
+                    TokenKind::MinusEqual
+                } else {
+                    TokenKind::Minus
+                };
+                self.add_token(token_kind, self.source[self.start..self.current].into());
+            }
+            '+' => {
+                let token_kind = if self.char_match('=') {
+                    self.advance();
+                    TokenKind::PlusEqual
+                } else {
+                    TokenKind::Plus
+                };
+                self.add_token(token_kind, self.source[self.start..self.current].into());
+            }
             ';' => self.add_token(
                 TokenKind::Semicolon,
                 self.source[self.start..self.current].into(),
             ),
-            '*' => self.add_token(
-                TokenKind::Star,
+            ':' => self.add_token(
+                TokenKind::Colon,
                 self.source[self.start..self.current].into(),
             ),
+            '?' => {
+                let token_kind = if self.char_match('?') {
+                    self.advance();
+                    TokenKind::QuestionQuestion
+                } else {
+                    TokenKind::Question
+                };
+                self.add_token(token_kind, self.source[self.start..self.current].into());
+            }
+            '*' => {
+                let token_kind = if self.char_match('*') {
+                    self.advance();
+                    TokenKind::StarStar
+                } else if self.char_match('=') {
+                    self.advance();
+                    TokenKind::StarEqual
+                } else {
+                    TokenKind::Star
+                };
+                self.add_token(token_kind, self.source[self.start..self.current].into());
+            }
+            '%' => {
+                let token_kind = if self.char_match('=') {
+                    self.advance();
+                    TokenKind::PercentEqual
+                } else {
+                    TokenKind::Percent
+                };
+                self.add_token(token_kind, self.source[self.start..self.current].into());
+            }
+            '~' => {
+                if self.char_match('/') {
+                    self.advance();
+                    self.add_token(
+                        TokenKind::TildeSlash,
+                        self.source[self.start..self.current].into(),
+                    );
+                } else {
+                    self.error("Unexpected character");
+                }
+            }
             '!' => {
                 let token_kind = if self.char_match('=') {
                     self.advance();
@@ -89,6 +190,9 @@ impl<'a> Scanner<'a> {
                 let token_kind = if self.char_match('=') {
                     self.advance();
                     TokenKind::EqualEqual
+                } else if self.char_match('>') {
+                    self.advance();
+                    TokenKind::Arrow
                 } else {
                     TokenKind::Equal
                 };
@@ -117,6 +221,21 @@ impl<'a> Scanner<'a> {
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    self.comments.push(Comment {
+                        text: self.source[self.start..self.current].trim_end().to_string(),
+                        line: self.line,
+                        next_token: self.tokens.len(),
+                        trailing: self
+                            .tokens
+                            .last()
+                            .is_some_and(|token| token.line == self.line),
+                    });
+                } else if self.char_match('=') {
+                    self.advance();
+                    self.add_token(
+                        TokenKind::SlashEqual,
+                        self.source[self.start..self.current].into(),
+                    );
                 } else {
                     self.add_token(
                         TokenKind::Slash,
@@ -127,17 +246,16 @@ impl<'a> Scanner<'a> {
             ' ' | '\r' | '\t' => {}
             '\n' => {
                 self.line += 1;
+                self.line_start = self.current;
             }
             '"' => self.string(),
             _ => {
-                if Self::is_digit(c as u8) {
+                if Self::is_digit(c) {
                     self.number();
-                } else if Self::is_alpha(c as u8) {
+                } else if Self::is_alpha(c) {
                     self.identifier();
                 } else {
-                    const ERROR: &str = "Unexpected character";
-                    self.errors.push(format!("{} at line {}", ERROR, self.line));
-                    crate::error_line(self.line, ERROR);
+                    self.error("Unexpected character");
                 }
             }
         }
@@ -145,13 +263,23 @@ impl<'a> Scanner<'a> {
 
     /// Advances the current position in the source and returns the next character.
     ///
-    /// This method increments the `current` index and retrieves the next character
-    /// from the `source` starting at the updated position. If the `source` is empty
-    /// this method will panic.
+    /// This method retrieves the character at the `current` index and moves the index
+    /// past all of its bytes, so that it always stays on a character boundary. If the
+    /// `source` is empty this method will panic.
     fn advance(&mut self) -> char {
-        let old = self.current;
-        self.current += 1;
-        self.source[old..].chars().next().unwrap()
+        let c = self.source[self.current..].chars().next().unwrap();
+        self.current += c.len_utf8();
+        c
+    }
+
+    /// Records a scanning error at the current line and reports it.
+    fn error(&mut self, message: &str) {
+        self.errors.push(ScanError {
+            message: message.to_string(),
+            line: self.line,
+            offset: self.start,
+        });
+        crate::error_line(self.line, message);
     }
 
     fn add_token(&mut self, kind: TokenKind, literal: Literal) {
@@ -160,6 +288,8 @@ impl<'a> Scanner<'a> {
             literal,
             lexeme: self.source[self.start..self.current].into(),
             line: self.line,
+            column: self.column,
+            offset: self.start,
         });
     }
 
@@ -199,7 +329,7 @@ impl<'a> Scanner<'a> {
     /// current   peek   peek_next(returns this)
     ///
     fn peek_next(&mut self) -> char {
-        self.source[self.current..].chars().nth(1).unwrap()
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn string(&mut self) {
@@ -207,14 +337,15 @@ impl<'a> Scanner<'a> {
             if self.is_at_end() || '"' == self.peek() {
                 break;
             }
-            if '\n' == self.peek() {
+            if '\n' == self.advance() {
                 self.line += 1;
+                self.line_start = self.current;
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            crate::error_line(self.line, "Unterminated string.");
+            self.error("Unterminated string.");
+            return;
         }
 
         self.advance();
@@ -224,38 +355,113 @@ impl<'a> Scanner<'a> {
         self.add_token(TokenKind::String, value.into());
     }
 
-    fn is_digit(c: u8) -> bool {
+    fn is_digit(c: char) -> bool {
         c.is_ascii_digit()
     }
 
-    fn is_alpha(c: u8) -> bool {
-        c.is_ascii_alphabetic() || c == b'_'
+    fn is_alpha(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == '_'
     }
 
-    fn is_alpha_numeric(c: u8) -> bool {
+    fn is_alpha_numeric(c: char) -> bool {
         Self::is_alpha(c) || Self::is_digit(c)
     }
 
+    /// Scans a numeric literal.
+    ///
+    /// Supports decimal literals with an optional fraction and exponent (`1.5e-3`),
+    /// hexadecimal (`0xFF`) and binary (`0b1010`) literals, and `_` separators
+    /// between digits (`1_000_000`). Malformed literals are reported as scanner errors.
     fn number(&mut self) {
-        while !self.is_at_end() && Scanner::is_digit(self.peek() as u8) {
+        if &self.source[self.start..self.current] == "0"
+            && !self.is_at_end()
+            && matches!(self.peek(), 'x' | 'X' | 'b' | 'B')
+        {
+            let radix = if matches!(self.peek(), 'x' | 'X') {
+                16
+            } else {
+                2
+            };
+            self.advance();
+            self.radix_number(radix);
+            return;
+        }
+
+        self.digits(10);
+
+        if !self.is_at_end() && self.peek() == '.' && Scanner::is_digit(self.peek_next()) {
             self.advance();
+            self.digits(10);
         }
 
-        if !self.is_at_end() && self.peek() == '.' && Scanner::is_digit(self.peek_next() as u8) {
+        if !self.is_at_end() && matches!(self.peek(), 'e' | 'E') {
             self.advance();
-            while !self.is_at_end() && Scanner::is_digit(self.peek() as u8) {
+            if !self.is_at_end() && matches!(self.peek(), '+' | '-') {
                 self.advance();
             }
+            if self.is_at_end() || !Scanner::is_digit(self.peek()) {
+                self.error("Expect digits after exponent in numeric literal.");
+                return;
+            }
+            self.digits(10);
         }
 
-        self.add_token(
-            TokenKind::Number,
-            Literal::Number(self.source[self.start..self.current].parse().unwrap()),
-        );
+        let lexeme = &self.source[self.start..self.current];
+        if !Self::valid_separators(lexeme, 10) {
+            self.error("Invalid '_' separator in numeric literal.");
+            return;
+        }
+
+        let text: String = lexeme.chars().filter(|c| *c != '_').collect();
+        match text.parse() {
+            Ok(value) => self.add_token(TokenKind::Number, Literal::Number(value)),
+            Err(_) => self.error("Invalid numeric literal."),
+        }
+    }
+
+    /// Scans the digits of a hexadecimal or binary literal, after its `0x`/`0b` prefix.
+    fn radix_number(&mut self, radix: u32) {
+        let digits_start = self.current;
+        self.digits(radix);
+
+        let digits = &self.source[digits_start..self.current];
+        if digits.is_empty() {
+            self.error("Expect digits after numeric literal prefix.");
+            return;
+        }
+        if !Self::valid_separators(digits, radix) {
+            self.error("Invalid '_' separator in numeric literal.");
+            return;
+        }
+
+        let value = digits
+            .chars()
+            .filter_map(|c| c.to_digit(radix))
+            .fold(0f64, |acc, d| acc * radix as f64 + d as f64);
+        self.add_token(TokenKind::Number, Literal::Number(value));
+    }
+
+    /// Consumes digits of the given radix, along with any `_` separators.
+    fn digits(&mut self, radix: u32) {
+        while !self.is_at_end() && (self.peek().is_digit(radix) || self.peek() == '_') {
+            self.advance();
+        }
+    }
+
+    /// Separators must sit between two digits: no leading, trailing, or repeated `_`.
+    fn valid_separators(text: &str, radix: u32) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        chars.iter().enumerate().all(|(i, c)| {
+            *c != '_'
+                || (i > 0
+                    && i + 1 < chars.len()
+                    && chars[i - 1].is_digit(radix)
+                    && chars[i + 1].is_digit(radix))
+        })
     }
 
     fn identifier(&mut self) {
-        while !self.is_at_end() && Self::is_alpha_numeric(self.peek() as u8) {
+        while !self.is_at_end() && Self::is_alpha_numeric(self.peek()) {
             self.advance();
         }
 
@@ -474,4 +680,124 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    pub fn test_numeric_literals() {
+        let test_cases = vec![
+            TestCase {
+                input: "123",
+                expected: 123.0,
+            },
+            TestCase {
+                input: "123.45",
+                expected: 123.45,
+            },
+            TestCase {
+                input: "0xFF",
+                expected: 255.0,
+            },
+            TestCase {
+                input: "0Xff",
+                expected: 255.0,
+            },
+            TestCase {
+                input: "0b1010",
+                expected: 10.0,
+            },
+            TestCase {
+                input: "1_000_000",
+                expected: 1_000_000.0,
+            },
+            TestCase {
+                input: "0xFF_FF",
+                expected: 65535.0,
+            },
+            TestCase {
+                input: "1.5e-3",
+                expected: 0.0015,
+            },
+            TestCase {
+                input: "2E+2",
+                expected: 200.0,
+            },
+            TestCase {
+                input: "1e3",
+                expected: 1000.0,
+            },
+        ];
+
+        for case in test_cases {
+            let mut scanner = Scanner::new(case.input);
+            let tokens = scanner.scan_tokens().clone();
+            assert!(scanner.errors.is_empty(), "Failed on input: {}", case.input);
+            assert_eq!(tokens.len(), 2, "Failed on input: {}", case.input);
+            match tokens[0].literal {
+                Literal::Number(n) => {
+                    assert_eq!(n, case.expected, "Failed on input: {}", case.input)
+                }
+                _ => panic!("Expected number literal for input: {}", case.input),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_malformed_numeric_literals() {
+        for input in ["0x", "0b", "1e", "1e+", "1_", "1__0", "0x_F", "1_.5"] {
+            let mut scanner = Scanner::new(input);
+            scanner.scan_tokens();
+            assert_eq!(scanner.errors.len(), 1, "Failed on input: {}", input);
+        }
+    }
+
+    #[test]
+    pub fn test_non_ascii() {
+        let mut scanner = Scanner::new("print \"café\"; // naïve\nš;");
+        let tokens = scanner.scan_tokens().clone();
+        assert_eq!(tokens[1].lexeme, "\"café\"");
+        assert_eq!((tokens[2].lexeme.as_str(), tokens[2].offset), (";", 13));
+        assert_eq!(scanner.comments()[0].text, "// naïve");
+        // A character that is not ASCII is never part of an identifier.
+        assert_eq!(
+            scanner.errors(),
+            &[ScanError {
+                message: "Unexpected character".to_string(),
+                line: 2,
+                offset: 25,
+            }]
+        );
+        assert_eq!(tokens[3].kind, TokenKind::Semicolon);
+        assert_eq!(
+            (tokens[3].line, tokens[3].column, tokens[3].offset),
+            (2, 2, 27)
+        );
+    }
+
+    #[test]
+    pub fn test_comments() {
+        let mut scanner = Scanner::new("// first\nprint 1; // second  \n//third");
+        scanner.scan_tokens();
+        assert_eq!(
+            scanner.comments(),
+            &[
+                Comment {
+                    text: "// first".to_string(),
+                    line: 1,
+                    next_token: 0,
+                    trailing: false,
+                },
+                Comment {
+                    text: "// second".to_string(),
+                    line: 2,
+                    next_token: 3,
+                    trailing: true,
+                },
+                Comment {
+                    text: "//third".to_string(),
+                    line: 3,
+                    next_token: 3,
+                    trailing: false,
+                },
+            ]
+        );
+    }
 }
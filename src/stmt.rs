@@ -1,4 +1,5 @@
 use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
 
 use crate::{expr::Expr, token::Token};
 
@@ -6,20 +7,55 @@ use crate::{expr::Expr, token::Token};
 pub enum Stmt {
     Expr(Expr),
     If {
+        keyword: Token,
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
-    Print(Expr),
+    Print {
+        keyword: Token,
+        expression: Expr,
+    },
     Var {
         name: Token,
         initializer: Expr,
     },
     While {
+        keyword: Token,
         condition: Expr,
         body: Box<Stmt>,
     },
     Block(Vec<Stmt>),
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: Rc<Vec<Stmt>>,
+    },
+    Return {
+        keyword: Token,
+        value: Expr,
+    },
+}
+
+impl Stmt {
+    /// Returns the statement's keyword or name token, or that of its first child for
+    /// expression statements and blocks.
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Stmt::Expr(expr) => expr.token(),
+            Stmt::If { keyword, .. }
+            | Stmt::Print { keyword, .. }
+            | Stmt::While { keyword, .. }
+            | Stmt::Return { keyword, .. } => Some(keyword),
+            Stmt::Var { name, .. } | Stmt::Function { name, .. } => Some(name),
+            Stmt::Block(stmts) => stmts.iter().find_map(Stmt::token),
+        }
+    }
+
+    /// Returns the source line the statement starts on, from its [token](Stmt::token).
+    pub fn line(&self) -> Option<usize> {
+        self.token().map(|token| token.line)
+    }
 }
 
 impl Display for Stmt {
@@ -30,6 +66,7 @@ impl Display for Stmt {
                 condition,
                 then_branch,
                 else_branch,
+                ..
             } => {
                 write!(f, "if {} then {} ", condition, then_branch)?;
                 if let Some(else_branch) = else_branch {
@@ -37,11 +74,13 @@ impl Display for Stmt {
                 }
                 Ok(())
             }
-            Stmt::Print(expr) => write!(f, "print {}", expr),
+            Stmt::Print { expression, .. } => write!(f, "print {}", expression),
             Stmt::Var { name, initializer } => {
                 write!(f, "var {} = {}", name.lexeme, initializer)
             }
-            Stmt::While { condition, body } => {
+            Stmt::While {
+                condition, body, ..
+            } => {
                 write!(f, "while {} {}", condition, body)
             }
             Stmt::Block(statements) => {
@@ -51,6 +90,21 @@ impl Display for Stmt {
                 }
                 write!(f, "}}")
             }
+            Stmt::Function { name, params, body } => {
+                write!(f, "fun {}", name.lexeme)?;
+                write_function(f, params, body)
+            }
+            Stmt::Return { value, .. } => write!(f, "return {}", value),
         }
     }
 }
+
+/// Writes the parameter list and body shared by function declarations and lambdas.
+pub(crate) fn write_function(f: &mut Formatter, params: &[Token], body: &[Stmt]) -> fmt::Result {
+    let params: Vec<&str> = params.iter().map(|param| param.lexeme.as_str()).collect();
+    write!(f, "({}) {{ ", params.join(" "))?;
+    for stmt in body {
+        write!(f, "{} ", stmt)?;
+    }
+    write!(f, "}}")
+}
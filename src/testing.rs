@@ -0,0 +1,299 @@
+//! Unit tests written in Lox: every `test_*` function declared at the top level of a
+//! `*_test.lox` file is a test, which passes unless it stops with an error.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    coverage::Coverage,
+    environment::Environment,
+    interpreter::Interpreter,
+    parser::{ParseError, Parser},
+    scanner::Scanner,
+    stmt::Stmt,
+    ErrorKind, RuntimeError,
+};
+
+/// The suffix naming files that contain tests.
+pub const TEST_FILE_SUFFIX: &str = "_test.lox";
+/// The prefix naming test functions.
+pub const TEST_PREFIX: &str = "test_";
+
+/// Why a test, or a whole test file, failed.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    /// The line the failure happened on, when known.
+    pub line: Option<usize>,
+    pub message: String,
+    /// Whether an assertion failed, rather than the test stopping with another error.
+    pub assertion: bool,
+}
+
+impl From<RuntimeError> for Failure {
+    fn from(err: RuntimeError) -> Self {
+        Failure {
+            line: err.token.map(|token| token.line),
+            message: err.message,
+            assertion: err.kind == ErrorKind::AssertionFailed,
+        }
+    }
+}
+
+/// The outcome of one test function.
+#[derive(Debug, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub failure: Option<Failure>,
+    /// What the test printed.
+    pub output: String,
+}
+
+/// Returns the test files among `paths`, searching directories recursively, sorted.
+pub fn discover(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let entries = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            files.extend(discover(&entries)?);
+        } else if is_test_file(path) {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_test_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(TEST_FILE_SUFFIX))
+}
+
+/// Runs every test function in `source`, in the order they are declared.
+///
+/// Each test gets a fresh interpreter, which runs the whole file to define its globals
+/// and then calls the test. Fails as a whole if the file does not parse.
+pub fn run_tests(source: &str) -> Result<Vec<TestResult>, Failure> {
+    run_file(source, &mut None)
+}
+
+/// Runs the tests as with [`run_tests`], also returning the coverage of the file
+/// accumulated over all of its tests.
+pub fn run_tests_with_coverage(source: &str) -> Result<(Vec<TestResult>, Coverage), Failure> {
+    let mut coverage = Some(Coverage::default());
+    let results = run_file(source, &mut coverage)?;
+    Ok((results, coverage.unwrap_or_default()))
+}
+
+/// Records coverage into `coverage` unless it is `None`.
+fn run_file(source: &str, coverage: &mut Option<Coverage>) -> Result<Vec<TestResult>, Failure> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().clone();
+    let stmts = Parser::new(&tokens).parse().map_err(|err| Failure {
+        line: err.downcast_ref::<ParseError>().map(|err| err.token.line),
+        message: err.to_string(),
+        assertion: false,
+    })?;
+    if let Some(error) = scanner.errors().first() {
+        return Err(Failure {
+            line: Some(error.line),
+            message: error.message.clone(),
+            assertion: false,
+        });
+    }
+
+    if coverage.is_some() {
+        *coverage = Some(Coverage::new(&stmts));
+    }
+
+    let tests = stmts.iter().filter_map(|stmt| match stmt {
+        Stmt::Function { name, params, .. } if name.lexeme.starts_with(TEST_PREFIX) => {
+            Some((name, params.len()))
+        }
+        _ => None,
+    });
+    Ok(tests
+        .map(|(name, arity)| {
+            if arity > 0 {
+                return TestResult {
+                    name: name.lexeme.clone(),
+                    failure: Some(Failure {
+                        line: Some(name.line),
+                        message: "Test functions can't have parameters.".to_string(),
+                        assertion: false,
+                    }),
+                    output: String::new(),
+                };
+            }
+            run_test(&stmts, &name.lexeme, coverage)
+        })
+        .collect())
+}
+
+fn run_test(stmts: &[Stmt], name: &str, coverage: &mut Option<Coverage>) -> TestResult {
+    let call = format!("{}()", name);
+    let mut scanner = Scanner::new(&call);
+    let call = Parser::new(scanner.scan_tokens())
+        .parse_expression()
+        .and_then(Result::ok)
+        .expect("a call to a test function parses");
+
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    // Each test adds to the coverage of the tests before it.
+    if let Some(coverage) = coverage.take() {
+        interpreter.set_coverage(coverage);
+    }
+    let result = interpreter
+        .interpret(stmts)
+        .and_then(|_| interpreter.evaluate(&call));
+    *coverage = interpreter.take_coverage();
+    TestResult {
+        name: name.to_string(),
+        failure: result.err().map(Failure::from),
+        output: String::from_utf8_lossy(&output).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::tests::test_case;
+
+    fn failure(line: usize, message: &str, assertion: bool) -> Option<Failure> {
+        Some(Failure {
+            line: Some(line),
+            message: message.to_string(),
+            assertion,
+        })
+    }
+
+    #[test]
+    fn test_run_tests() {
+        let source = r#"var calls = 0;
+fun add(a, b) { return a + b; }
+fun test_add() {
+  calls = calls + 1;
+  assert_eq(add(1, 2), 3);
+  print calls;
+}
+fun test_isolated() {
+  calls = calls + 1;
+  assert(calls == 1, "globals are fresh");
+}
+fun test_wrong_sum() {
+  print "adding";
+  assert_eq(add(2, 2), 5);
+}
+fun test_assert() {
+  assert(1 > 2, "one is not greater");
+}
+fun test_error() { return -"x"; }
+fun test_params(a) {}
+fun helper() { assert(false, "not a test"); }
+"#;
+        let results = run_tests(source).unwrap();
+        let names: Vec<&str> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "test_add",
+                "test_isolated",
+                "test_wrong_sum",
+                "test_assert",
+                "test_error",
+                "test_params"
+            ]
+        );
+
+        let test_cases = vec![
+            test_case::TestCase {
+                input: &results[0],
+                expected: (None, "1\n"),
+            },
+            test_case::TestCase {
+                input: &results[1],
+                expected: (None, ""),
+            },
+            test_case::TestCase {
+                input: &results[2],
+                expected: (
+                    failure(14, "Assertion failed: expected 5, got 4.", true),
+                    "adding\n",
+                ),
+            },
+            test_case::TestCase {
+                input: &results[3],
+                expected: (
+                    failure(17, "Assertion failed: one is not greater", true),
+                    "",
+                ),
+            },
+            test_case::TestCase {
+                input: &results[5],
+                expected: (
+                    failure(20, "Test functions can't have parameters.", false),
+                    "",
+                ),
+            },
+        ];
+        for case in test_cases {
+            let (failure, output) = case.expected;
+            assert_eq!(case.input.failure, failure, "{}", case.input.name);
+            assert_eq!(case.input.output, output, "{}", case.input.name);
+        }
+
+        let error = results[4].failure.as_ref().unwrap();
+        assert!(!error.assertion);
+        assert_eq!(error.line, Some(19));
+    }
+
+    #[test]
+    fn test_coverage_accumulates_over_tests() {
+        let source = "fun sign(x) {
+  if (x < 0) return -1;
+  return 1;
+}
+fun test_negative() { assert_eq(sign(-2), -1); }
+fun test_positive() { assert_eq(sign(2), 1); }
+fun unused() {
+  return 0;
+}
+";
+        let (results, coverage) = run_tests_with_coverage(source).unwrap();
+        assert!(results.iter().all(|result| result.failure.is_none()));
+        assert_eq!(coverage.uncovered_lines(), vec![(8, 8)]);
+        assert_eq!(
+            coverage.summary().to_string(),
+            "lines 85.7% (6/7), branches 100.0% (2/2)"
+        );
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let failure = run_tests("fun test_x() {").unwrap_err();
+        assert_eq!(failure.message, "Expect '}' after block.");
+        assert_eq!(failure.line, Some(1));
+        assert!(run_tests("fun test_x() {} @").is_err());
+        assert_eq!(run_tests("print 1;"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = std::env::temp_dir().join(format!("rlox-discover-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for file in ["a_test.lox", "b.lox", "nested/c_test.lox"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let files = discover(std::slice::from_ref(&dir)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            files,
+            [dir.join("a_test.lox"), dir.join("nested/c_test.lox")]
+        );
+    }
+}
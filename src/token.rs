@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 use std::sync::LazyLock;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -12,8 +13,10 @@ pub enum TokenKind {
     Minus,
     Plus,
     Semicolon,
+    Colon,
     Slash,
     Star,
+    Percent,
 
     Bang,
     BangEqual,
@@ -23,6 +26,16 @@ pub enum TokenKind {
     GreaterEqual,
     Less,
     LessEqual,
+    StarStar,
+    TildeSlash,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
+    Question,
+    QuestionQuestion,
+    Arrow,
 
     Identifier,
     String,
@@ -74,6 +87,21 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Literal,
     pub line: usize,
+    /// The 1-based column of the lexeme's first character, counted in characters from
+    /// the start of its line.
+    pub column: usize,
+    /// The byte offset of the lexeme in the source.
+    pub offset: usize,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} '{}' {:?} [line {}]",
+            self.kind, self.lexeme, self.literal, self.line
+        )
+    }
 }
 
 pub(crate) static KEYWORD_MAP: LazyLock<HashMap<&str, TokenKind>> = LazyLock::new(|| {
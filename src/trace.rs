@@ -0,0 +1,204 @@
+//! Execution traces, logging what a running program does for diagnosing it afterwards.
+//!
+//! Every event is one line, prefixed with the source line it happened on and indented
+//! by the depth of the call it happened in:
+//!
+//! ```text
+//! [line 4] stmt: var x = (call f 1)
+//! [line 4] call f(1)
+//!   [line 1] define a = 1
+//!   [line 2] stmt: return (+ a 1)
+//! [line 4] return f -> 2
+//! [line 4] define x = 2
+//! ```
+//!
+//! Statements are logged in their syntax tree form, except blocks, whose statements
+//! are logged instead. Functions are named as in profiles, so a lambda is
+//! `<lambda:N>` after the line its body starts on, both as a value and when called.
+
+use std::io::{self, Write};
+
+use crate::{callable::Callable, error::RuntimeError, expr::Literal, stmt::Stmt};
+
+pub struct Tracer {
+    output: Box<dyn Write>,
+    /// The number of calls in progress.
+    depth: usize,
+    /// The first error writing the trace. Tracing stops after it, but the program
+    /// goes on.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Tracer {
+            output,
+            depth: 0,
+            error: None,
+        }
+    }
+
+    fn log(&mut self, line: Option<usize>, event: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        let indent = "  ".repeat(self.depth);
+        let written = match line {
+            Some(line) => writeln!(self.output, "{}[line {}] {}", indent, line, event),
+            None => writeln!(self.output, "{}{}", indent, event),
+        };
+        self.error = written.err();
+    }
+
+    /// Logs a statement about to run.
+    pub(crate) fn stmt(&mut self, stmt: &Stmt) {
+        if !matches!(stmt, Stmt::Block(_)) {
+            let event = format!("stmt: {}", stmt);
+            self.log(stmt.line(), event.trim_end());
+        }
+    }
+
+    /// Logs a variable being defined in the innermost scope.
+    pub(crate) fn define(&mut self, line: usize, name: &str, value: &Literal) {
+        self.log(
+            Some(line),
+            &format!("define {} = {}", name, format_value(value)),
+        );
+    }
+
+    /// Logs an existing variable being assigned.
+    pub(crate) fn assign(&mut self, line: usize, name: &str, value: &Literal) {
+        self.log(
+            Some(line),
+            &format!("assign {} = {}", name, format_value(value)),
+        );
+    }
+
+    /// Logs a call to the function named `name`, made on `line`, which runs until
+    /// [`Tracer::exit`].
+    pub(crate) fn enter(&mut self, line: usize, name: &str, arguments: &[Literal]) {
+        let arguments: Vec<String> = arguments.iter().map(format_value).collect();
+        self.log(
+            Some(line),
+            &format!("call {}({})", name, arguments.join(", ")),
+        );
+        self.depth += 1;
+    }
+
+    /// Logs the innermost call returning a value or stopping with an error.
+    pub(crate) fn exit(&mut self, line: usize, name: &str, result: &Result<Literal, RuntimeError>) {
+        self.depth = self.depth.saturating_sub(1);
+        let event = match result {
+            Ok(value) => format!("return {} -> {}", name, format_value(value)),
+            Err(err) => format!("error in {}: {}", name, err.message),
+        };
+        self.log(Some(line), &event);
+    }
+
+    /// Flushes the trace, returning the first error writing it, if any.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.output.flush(),
+        }
+    }
+}
+
+/// Writes a value as it prints, except that lambdas are named like the calls to them.
+fn format_value(value: &Literal) -> String {
+    match value {
+        Literal::Callable(Callable::Function(function)) if function.name.is_none() => {
+            format!("<fn {}>", function.report_name())
+        }
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        environment::Environment, interpreter::Interpreter, parser::Parser, scanner::Scanner,
+        utils::tests::test_case,
+    };
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(source: &str) -> String {
+        let mut scanner = Scanner::new(source);
+        let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut output = Vec::new();
+        let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+        interpreter.set_tracer(Tracer::new(Box::new(Shared(Rc::clone(&buffer)))));
+        // Errors are traced too.
+        let _ = interpreter.interpret(&stmts);
+        interpreter.take_tracer().unwrap().finish().unwrap();
+        let trace = String::from_utf8(buffer.borrow().clone()).unwrap();
+        trace
+    }
+
+    #[test]
+    fn test_trace() {
+        let test_cases = vec![
+            test_case::TestCase {
+                input: "var x = 1;\nx = x + 1;",
+                expected: "[line 1] stmt: var x = 1
+[line 1] define x = 1
+[line 2] stmt: (= x (+ x 1))
+[line 2] assign x = 2
+",
+            },
+            test_case::TestCase {
+                input:
+                    "fun add(a, b) {\n  return a + b;\n}\nvar s = add(\"a\", \"b\" ?? 3);\n{ print s; }",
+                expected: "[line 1] stmt: fun add(a b) { return (+ a b) }
+[line 1] define add = <fn add>
+[line 4] stmt: var s = (call add \"a\" (?? \"b\" 3))
+[line 4] call add(\"a\", \"b\")
+  [line 1] define a = \"a\"
+  [line 1] define b = \"b\"
+  [line 2] stmt: return (+ a b)
+[line 4] return add -> \"ab\"
+[line 4] define s = \"ab\"
+[line 5] stmt: print s
+",
+            },
+            test_case::TestCase {
+                input: "var f = (x) => -x;\nf(\"a\");\nprint 1;",
+                expected: "[line 1] stmt: var f = (fun (x) { return (- x) })
+[line 1] define f = <fn <lambda:1>>
+[line 2] stmt: (call f \"a\")
+[line 2] call <lambda:1>(\"a\")
+  [line 1] define x = \"a\"
+  [line 1] stmt: return (- x)
+[line 2] error in <lambda:1>: Operand must be a number, got String(\"a\")
+",
+            },
+        ];
+
+        for case in test_cases {
+            assert_eq!(trace(case.input), case.expected, "{}", case.input);
+        }
+
+        let clock = trace("print clock() > 0;");
+        assert!(
+            clock.starts_with("[line 1] stmt: print (> (call clock) 0)\n[line 1] call clock()\n"),
+            "{}",
+            clock
+        );
+        assert!(clock.contains("[line 1] return clock -> "), "{}", clock);
+    }
+}
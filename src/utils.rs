@@ -8,6 +8,8 @@ pub mod tests {
             lexeme: "".to_string(),
             literal: token::Literal::None,
             line,
+            column: 0,
+            offset: 0,
         }
     }
 
@@ -19,36 +21,48 @@ pub mod tests {
                     kind: TokenKind::Plus,
                     lexeme: "+".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 "*" => Token {
                     literal: value.into(),
                     kind: TokenKind::Star,
                     lexeme: "*".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 "-" => Token {
                     literal: value.into(),
                     kind: TokenKind::Minus,
                     lexeme: "-".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 "/" => Token {
                     literal: value.into(),
                     kind: TokenKind::Slash,
                     lexeme: "/".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 "and" => Token {
                     literal: value.into(),
                     kind: TokenKind::And,
                     lexeme: "and".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 "or" => Token {
                     literal: value.into(),
                     kind: TokenKind::Or,
                     lexeme: "or".to_string(),
                     line: 1,
+                    column: 0,
+                    offset: 0,
                 },
                 _ => panic!("{} not a valid token value", value),
             }
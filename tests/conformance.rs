@@ -0,0 +1,184 @@
+//! Runs every `.lox` file under `tests/lox` through the `rlox` binary and checks its
+//! output against the expectations written in the file's comments, in the format of
+//! the Crafting Interpreters test suite:
+//!
+//! - `// expect: <line>` expects the next line of standard output.
+//! - `// expect runtime error: <message>` expects the program to stop with a runtime
+//!   error on the comment's line.
+//! - `// [line N] Error<details>` expects a scan or parse error on line N, and
+//!   `// Error<details>` one on the comment's line.
+//!
+//! A file with an expected error must fail with the matching exit code. New regression
+//! tests only need a new `.lox` file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// Error";
+const EXPECT_ERROR_AT_LINE: &str = "// [line ";
+
+/// What a test file expects from running it.
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    output: Vec<String>,
+    /// Scan and parse errors, as reported: `[line N] Error at 'x': message`.
+    errors: Vec<String>,
+    /// The message and line of the runtime error.
+    runtime_error: Option<(String, usize)>,
+}
+
+fn parse_expectations(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        if let Some((_, output)) = line.split_once(EXPECT) {
+            expectations.output.push(output.to_string());
+        } else if let Some((_, message)) = line.split_once(EXPECT_RUNTIME_ERROR) {
+            expectations.runtime_error = Some((message.to_string(), line_number));
+        } else if let Some((_, error)) = line.split_once(EXPECT_ERROR_AT_LINE) {
+            expectations.errors.push(format!("[line {}", error));
+        } else if let Some((_, details)) = line.split_once(EXPECT_ERROR) {
+            let error = format!("[line {}] Error{}", line_number, details);
+            expectations.errors.push(error);
+        }
+    }
+    expectations
+}
+
+/// Runs one test file, returning why it failed, if it did.
+fn check(path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path).expect("test file is readable");
+    let expectations = parse_expectations(&source);
+    let result = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg(path)
+        .output()
+        .expect("rlox runs");
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let exit_code = result.status.code();
+    let mut failures = Vec::new();
+
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expectations.output {
+        failures.push(format!(
+            "expected output {:?}, got {:?}",
+            expectations.output, output
+        ));
+    }
+
+    if !expectations.errors.is_empty() {
+        let mut expected: Vec<&str> = expectations.errors.iter().map(String::as_str).collect();
+        let mut errors: Vec<&str> = stderr
+            .lines()
+            .filter(|line| line.starts_with("[line ") && line.contains("] Error"))
+            .collect();
+        expected.sort_unstable();
+        errors.sort_unstable();
+        if errors != expected {
+            failures.push(format!("expected errors {:?}, got {:?}", expected, errors));
+        }
+        if exit_code != Some(65) {
+            failures.push(format!("expected exit code 65, got {:?}", exit_code));
+        }
+    } else if let Some((message, line)) = &expectations.runtime_error {
+        let mut lines = stderr.lines();
+        if lines.next() != Some(message.as_str()) {
+            failures.push(format!(
+                "expected runtime error {:?}, got {:?}",
+                message, stderr
+            ));
+        } else if lines.next().map(str::trim) != Some(format!("[line {}]", line).as_str()) {
+            failures.push(format!(
+                "expected runtime error on line {}, got {:?}",
+                line, stderr
+            ));
+        }
+        if exit_code != Some(70) {
+            failures.push(format!("expected exit code 70, got {:?}", exit_code));
+        }
+    } else {
+        if !stderr.is_empty() {
+            failures.push(format!("unexpected error output {:?}", stderr));
+        }
+        if exit_code != Some(0) {
+            failures.push(format!("expected exit code 0, got {:?}", exit_code));
+        }
+    }
+    failures
+}
+
+/// Returns every `.lox` file under `dir`, sorted.
+fn lox_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).expect("test directory is readable") {
+        let path = entry.expect("test directory is readable").path();
+        if path.is_dir() {
+            files.extend(lox_files(&path));
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn test_conformance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let files = lox_files(&dir);
+    assert!(!files.is_empty(), "no test files in {}", dir.display());
+
+    let mut failed = 0;
+    for path in &files {
+        let name = path.strip_prefix(&dir).unwrap_or(path).display();
+        let failures = check(path);
+        if failures.is_empty() {
+            println!("PASS {}", name);
+        } else {
+            failed += 1;
+            println!("FAIL {}", name);
+            for failure in failures {
+                println!("     {}", failure);
+            }
+        }
+    }
+    println!("{} passed, {} failed", files.len() - failed, failed);
+    assert_eq!(failed, 0, "{} of {} test files failed", failed, files.len());
+}
+
+#[test]
+fn test_parse_expectations() {
+    let source = "print 1; // expect: 1
+// [line 3] Error at 'x': Bad.
+a + 1 = 2; // Error at '=': Invalid assignment target.
+print x; // expect runtime error: Undefined variable 'x'
+";
+    assert_eq!(
+        parse_expectations(source),
+        Expectations {
+            output: vec!["1".to_string()],
+            errors: vec![
+                "[line 3] Error at 'x': Bad.".to_string(),
+                "[line 3] Error at '=': Invalid assignment target.".to_string(),
+            ],
+            runtime_error: Some(("Undefined variable 'x'".to_string(), 4)),
+        }
+    );
+}
+
+#[test]
+fn test_check_reports_mismatches() {
+    let path = std::env::temp_dir().join(format!("rlox-conformance-{}.lox", std::process::id()));
+    fs::write(&path, "print 1; // expect: 2\nprint nil; // expect: nil\n").unwrap();
+    let failures = check(&path);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        failures,
+        vec![r#"expected output ["2", "nil"], got ["1", "nil"]"#.to_string()]
+    );
+}
@@ -0,0 +1,288 @@
+use std::{cell::RefCell, env, fs, io, rc::Rc};
+
+use rlox::{dap, lsp};
+use serde_json::{json, Value};
+
+const SOURCE: &str = "var greeting = \"hi\";
+fun greet(name) {
+  var message = greeting + \" \" + name;
+  return message;
+}
+print greet(\"lox\");
+";
+
+/// An output stream that can still be read after the adapter takes ownership of it.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes `source` to a script file named after the test, and returns its path.
+fn script(name: &str, source: &str) -> String {
+    let path = env::temp_dir().join(format!("rlox-dap-{}-{}.lox", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// Replays a recorded client session, numbering the requests, and returns every
+/// message the adapter sent back.
+fn run(requests: &[(&str, Value)]) -> Vec<Value> {
+    let mut input = Vec::new();
+    for (seq, (command, arguments)) in requests.iter().enumerate() {
+        let request = json!({
+            "seq": seq + 1,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        lsp::write_message(&mut input, &request).unwrap();
+    }
+
+    let output = Shared::default();
+    dap::run(io::Cursor::new(input), output.clone()).unwrap();
+
+    let bytes = output.0.borrow().clone();
+    let mut reader = &bytes[..];
+    let mut messages = Vec::new();
+    while let Some(message) = lsp::read_message(&mut reader).unwrap() {
+        messages.push(message);
+    }
+    messages
+}
+
+fn response(messages: &[Value], request_seq: u64) -> &Value {
+    messages
+        .iter()
+        .find(|message| message["type"] == "response" && message["request_seq"] == request_seq)
+        .unwrap_or_else(|| panic!("no response to request {}", request_seq))
+}
+
+fn events<'a>(messages: &'a [Value], event: &str) -> Vec<&'a Value> {
+    messages
+        .iter()
+        .filter(|message| message["type"] == "event" && message["event"] == event)
+        .map(|message| &message["body"])
+        .collect()
+}
+
+fn launch(program: &str, stop_on_entry: bool) -> (&'static str, Value) {
+    (
+        "launch",
+        json!({ "program": program, "stopOnEntry": stop_on_entry }),
+    )
+}
+
+fn breakpoints(lines: &[u64]) -> (&'static str, Value) {
+    let breakpoints: Vec<Value> = lines.iter().map(|line| json!({ "line": line })).collect();
+    (
+        "setBreakpoints",
+        json!({ "source": { "path": "test.lox" }, "breakpoints": breakpoints }),
+    )
+}
+
+fn stdout(messages: &[Value]) -> String {
+    events(messages, "output")
+        .iter()
+        .filter(|body| body["category"] == "stdout")
+        .map(|body| body["output"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_breakpoint_session() {
+    let program = script("breakpoint", SOURCE);
+    let messages = run(&[
+        ("initialize", json!({ "adapterID": "rlox" })),
+        launch(&program, false),
+        breakpoints(&[4]),
+        ("configurationDone", json!({})),
+        ("threads", json!({})),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("scopes", json!({ "frameId": 1 })),
+        ("variables", json!({ "variablesReference": 1 })),
+        (
+            "setVariable",
+            json!({ "variablesReference": 1, "name": "message", "value": "\"bye\"" }),
+        ),
+        ("continue", json!({ "threadId": 1 })),
+        ("disconnect", json!({})),
+    ]);
+
+    let seqs: Vec<u64> = messages
+        .iter()
+        .map(|m| m["seq"].as_u64().unwrap())
+        .collect();
+    assert_eq!(seqs, (1..=seqs.len() as u64).collect::<Vec<_>>());
+    for seq in 1..=11 {
+        assert_eq!(response(&messages, seq)["success"], true, "request {}", seq);
+    }
+
+    assert_eq!(response(&messages, 1)["body"]["supportsSetVariable"], true);
+    assert_eq!(messages[1]["event"], "initialized");
+    assert_eq!(
+        response(&messages, 3)["body"],
+        json!({ "breakpoints": [{ "verified": true, "line": 4 }] })
+    );
+    assert_eq!(
+        events(&messages, "stopped"),
+        vec![&json!({ "reason": "breakpoint", "threadId": 1, "allThreadsStopped": true })]
+    );
+    assert_eq!(
+        response(&messages, 5)["body"],
+        json!({ "threads": [{ "id": 1, "name": "main" }] })
+    );
+    assert_eq!(
+        response(&messages, 6)["body"],
+        json!({
+            "stackFrames": [
+                { "id": 1, "name": "greet", "line": 4, "column": 1, "source": { "path": program } },
+                { "id": 0, "name": "script", "line": 6, "column": 1, "source": { "path": program } },
+            ],
+            "totalFrames": 2,
+        })
+    );
+    assert_eq!(
+        response(&messages, 7)["body"],
+        json!({
+            "scopes": [
+                { "name": "Locals", "variablesReference": 1, "expensive": false },
+                { "name": "Globals", "variablesReference": 2, "expensive": false },
+            ],
+        })
+    );
+    assert_eq!(
+        response(&messages, 8)["body"],
+        json!({
+            "variables": [
+                { "name": "message", "value": "\"hi lox\"", "type": "string", "variablesReference": 0 },
+                { "name": "name", "value": "\"lox\"", "type": "string", "variablesReference": 0 },
+            ],
+        })
+    );
+    assert_eq!(
+        response(&messages, 9)["body"],
+        json!({ "value": "\"bye\"", "type": "string" })
+    );
+    assert_eq!(stdout(&messages), "bye\n");
+    assert_eq!(events(&messages, "exited"), vec![&json!({ "exitCode": 0 })]);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+}
+
+#[test]
+fn test_stepping_session() {
+    let program = script("stepping", &format!("{}print greeting;\n", SOURCE));
+    let messages = run(&[
+        ("initialize", json!({})),
+        launch(&program, true),
+        ("configurationDone", json!({})),
+        ("next", json!({ "threadId": 1 })),
+        ("next", json!({ "threadId": 1 })),
+        ("stepIn", json!({ "threadId": 1 })),
+        (
+            "evaluate",
+            json!({ "expression": "name", "context": "hover" }),
+        ),
+        (
+            "evaluate",
+            json!({ "expression": "greeting", "frameId": 0 }),
+        ),
+        ("evaluate", json!({ "expression": "missing" })),
+        ("stepOut", json!({ "threadId": 1 })),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("terminate", json!({})),
+        ("disconnect", json!({})),
+    ]);
+
+    let stops: Vec<&Value> = events(&messages, "stopped")
+        .into_iter()
+        .map(|body| &body["reason"])
+        .collect();
+    assert_eq!(stops, ["entry", "step", "step", "step", "step"]);
+    assert_eq!(response(&messages, 7)["body"]["result"], "\"lox\"");
+    assert_eq!(response(&messages, 8)["body"]["result"], "\"hi\"");
+    assert_eq!(response(&messages, 9)["success"], false);
+    assert_eq!(
+        response(&messages, 9)["message"],
+        "Undefined variable 'missing'."
+    );
+    // Stepping out of `greet` pauses at the next statement in the script.
+    let frames = &response(&messages, 11)["body"]["stackFrames"];
+    assert_eq!(frames.as_array().unwrap().len(), 1);
+    assert_eq!(frames[0]["line"], 7);
+
+    // Terminating stops the program before its last statement, and is not a failure.
+    assert_eq!(stdout(&messages), "hi lox\n");
+    assert_eq!(events(&messages, "exited"), vec![&json!({ "exitCode": 0 })]);
+    assert_eq!(response(&messages, 13)["success"], true);
+}
+
+#[test]
+fn test_errors() {
+    let messages = run(&[
+        ("initialize", json!({})),
+        launch("/nonexistent/rlox-dap.lox", false),
+        ("bogus", json!({})),
+        ("disconnect", json!({})),
+    ]);
+    assert_eq!(response(&messages, 2)["success"], false);
+    assert_eq!(
+        response(&messages, 2)["message"],
+        "Could not open: /nonexistent/rlox-dap.lox"
+    );
+    assert_eq!(
+        response(&messages, 3)["message"],
+        "Unsupported request 'bogus'."
+    );
+    assert!(events(&messages, "exited").is_empty());
+
+    let program = script("runtime-error", "print 1;\nprint -\"a\";\n");
+    let messages = run(&[
+        ("initialize", json!({})),
+        launch(&program, false),
+        ("configurationDone", json!({})),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("disconnect", json!({})),
+    ]);
+    let output: Vec<&Value> = events(&messages, "output");
+    assert_eq!(output[0], &json!({ "category": "stdout", "output": "1\n" }));
+    assert_eq!(output[1]["category"], "stderr");
+    assert!(output[1]["output"]
+        .as_str()
+        .unwrap()
+        .ends_with("[line 2]\n"));
+    assert_eq!(
+        events(&messages, "exited"),
+        vec![&json!({ "exitCode": 70 })]
+    );
+    assert_eq!(response(&messages, 4)["success"], false);
+    assert_eq!(response(&messages, 5)["success"], true);
+}
+
+#[test]
+fn test_disconnect_while_paused() {
+    let program = script("disconnect", SOURCE);
+    let messages = run(&[
+        ("initialize", json!({})),
+        launch(&program, true),
+        ("configurationDone", json!({})),
+        ("disconnect", json!({})),
+        ("threads", json!({})),
+    ]);
+
+    // Disconnecting ends the session at once: the program stops, and nothing after
+    // the disconnect is read.
+    assert_eq!(events(&messages, "stopped").len(), 1);
+    assert_eq!(response(&messages, 4)["success"], true);
+    assert_eq!(messages.last(), Some(response(&messages, 4)));
+    assert!(events(&messages, "exited").is_empty());
+    assert!(events(&messages, "terminated").is_empty());
+    assert_eq!(stdout(&messages), "");
+}
@@ -1,4 +1,14 @@
-use rlox::{environment::Environment, interpreter::Interpreter, RLox};
+use std::{env, fs, thread, time::Duration};
+
+use rlox::{
+    capabilities::{Capabilities, Capability},
+    environment::Environment,
+    interpreter::Interpreter,
+    limits::{Limits, STACK_SIZE},
+    parser::Parser,
+    scanner::Scanner,
+    ErrorKind, RLox, RuntimeError,
+};
 
 struct TestCase<'a> {
     source: &'a str,
@@ -58,6 +68,108 @@ fn test_scripts() {
             expected_output: b"42\n",
             description: "For loop with if statement",
         },
+        TestCase {
+            source: "print 0xFF + 0b10 + 1_000 + 1.5e1;",
+            expected_output: b"1272\n",
+            description: "Extended numeric literals",
+        },
+        TestCase {
+            source: "print 7 % 3; print -7 % 3; print 2 ** 10; print -2 ** 2; print 7 ~/ 2; print -7 ~/ 2;",
+            expected_output: b"1\n-1\n1024\n-4\n3\n-4\n",
+            description: "Modulo, exponent and integer division",
+        },
+        TestCase {
+            source: r#"
+            var i = 10;
+            i += 5; print i;
+            i -= 3; print i;
+            i *= 2; print i;
+            i /= 4; print i;
+            i %= 4; print i;
+            var s = "a";
+            s += "b";
+            print s;
+            print i += 1;
+            "#,
+            expected_output: b"15\n12\n24\n6\n2\nab\n3\n",
+            description: "Compound assignment",
+        },
+        TestCase {
+            source: r#"
+            var x = 3;
+            print x > 2 ? "big" : "small";
+            print x > 5 ? "big" : x > 1 ? "medium" : "small";
+            var calls = 0;
+            true ? nil : (calls = calls + 1);
+            print calls;
+            print nil ?? "default";
+            print false ?? "default";
+            print 0 ?? (calls = calls + 1);
+            print calls;
+            "#,
+            expected_output: b"big\nmedium\n0\ndefault\nfalse\n0\n0\n",
+            description: "Conditional and nil-coalescing expressions",
+        },
+        TestCase {
+            source: r#"
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            print fib(10);
+            print fib;
+            "#,
+            expected_output: b"55\n<fn fib>\n",
+            description: "Recursive function",
+        },
+        TestCase {
+            source: r#"
+            fun makeCounter() {
+                var count = 0;
+                return fun () {
+                    count += 1;
+                    return count;
+                };
+            }
+            var counter = makeCounter();
+            counter();
+            print counter();
+            "#,
+            expected_output: b"2\n",
+            description: "Lambda closing over a local variable",
+        },
+        TestCase {
+            source: r#"
+            fun apply(f, x) { return f(x); }
+            print apply((x) => x * 2, 21);
+            print apply(fun (x) { return x + 1; }, 1);
+            print apply((x) => { return x ** 2; }, 3);
+            var add = (a, b) => a + b;
+            print add(1, 2);
+            print (() => "called")();
+            var x = 4;
+            print (x) * 2;
+            print (x, add) => x;
+            "#,
+            expected_output: b"42\n2\n9\n3\ncalled\n8\n<fn>\n",
+            description: "Lambda expressions",
+        },
+        TestCase {
+            source: r#"
+            fun find() {
+                var i = 0;
+                while (true) {
+                    { if (i == 3) return i; }
+                    i += 1;
+                }
+            }
+            print find();
+            var i = "global";
+            print i;
+            "#,
+            expected_output: b"3\nglobal\n",
+            description: "Return unwinding through blocks",
+        },
     ];
 
     for TestCase {
@@ -74,3 +186,403 @@ fn test_scripts() {
         assert_eq!(output, expected_output, "{}", description);
     }
 }
+
+#[test]
+fn test_repl_echoes_expressions() {
+    let test_cases = vec![
+        TestCase {
+            source: "1 + 2",
+            expected_output: b"3\n",
+            description: "Expression without semicolon",
+        },
+        TestCase {
+            source: "1 + 2;",
+            expected_output: b"3\n",
+            description: "Expression with semicolon",
+        },
+        TestCase {
+            source: r#""a" + "b""#,
+            expected_output: b"ab\n",
+            description: "String expression",
+        },
+        TestCase {
+            source: "print 1;",
+            expected_output: b"1\n",
+            description: "Print statement is not echoed twice",
+        },
+        TestCase {
+            source: "var x = 1;",
+            expected_output: b"",
+            description: "Declarations are not echoed",
+        },
+        TestCase {
+            source: "1; 2;",
+            expected_output: b"",
+            description: "Multiple statements run as a script",
+        },
+        TestCase {
+            source: "fun f() { return 42; } ",
+            expected_output: b"",
+            description: "Function declaration is not echoed",
+        },
+    ];
+
+    for TestCase {
+        source,
+        expected_output,
+        description,
+    } in test_cases
+    {
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(Environment::default(), &mut output);
+        let mut rlox = RLox::new(interpreter);
+        rlox.run_repl(source);
+
+        assert_eq!(output, expected_output, "{}", description);
+    }
+}
+
+#[test]
+fn test_run_repl_errors() {
+    let test_cases = vec![
+        ("1 @ + 2", (true, false)),
+        ("1 +", (true, false)),
+        ("-nil", (false, true)),
+        ("var x = -nil;", (false, true)),
+        ("1 + 2", (false, false)),
+    ];
+
+    for (source, (had_error, had_runtime_error)) in test_cases {
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(Environment::default(), &mut output);
+        let mut rlox = RLox::new(interpreter);
+        rlox.run_repl(source);
+        assert_eq!(rlox.had_error, had_error, "{}", source);
+        assert_eq!(rlox.had_runtime_error, had_runtime_error, "{}", source);
+        drop(rlox);
+        if had_error || had_runtime_error {
+            assert!(output.is_empty(), "{}", source);
+        }
+    }
+}
+
+#[test]
+fn test_run_does_not_echo_expressions() {
+    let mut output = Vec::new();
+    let interpreter = Interpreter::new(Environment::default(), &mut output);
+    let mut rlox = RLox::new(interpreter);
+    rlox.run("1 + 2;");
+
+    assert!(output.is_empty());
+}
+
+/// Runs `source` with the given limits, returning the error that stopped it, if any.
+fn run_limited(source: &str, limits: Limits) -> Result<(), RuntimeError> {
+    let mut scanner = Scanner::new(source);
+    let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    interpreter.set_limits(limits);
+    interpreter.interpret(&stmts)
+}
+
+#[test]
+fn test_limits() {
+    let statements = Limits {
+        max_statements: Some(100),
+        ..Limits::default()
+    };
+    let err = run_limited("while (true) {}", statements.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::StatementLimit);
+    assert_eq!(
+        err.message,
+        "Exceeded the limit of 100 executed statements."
+    );
+    assert!(run_limited("for (var i = 0; i < 10; i = i + 1) {}", statements).is_ok());
+
+    let timeout = Limits {
+        timeout: Some(Duration::from_millis(50)),
+        ..Limits::default()
+    };
+    let err = run_limited("while (true) {}", timeout.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Timeout);
+    assert_eq!(err.message, "Exceeded the time limit of 50 ms.");
+    assert!(run_limited("print 1;", timeout).is_ok());
+
+    let depth = Limits {
+        max_call_depth: Some(10),
+        ..Limits::default()
+    };
+    let recursion = "fun f(n) { if (n > 0) f(n - 1); }";
+    let err = run_limited(&format!("{} f(10);", recursion), depth.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::CallDepth);
+    assert_eq!(err.message, "Exceeded the maximum call depth of 10.");
+    assert_eq!(err.token.map(|token| token.line), Some(1));
+    assert!(run_limited(&format!("{} f(9);", recursion), depth).is_ok());
+
+    let memory = Limits {
+        max_memory: Some(1 << 20),
+        ..Limits::default()
+    };
+    let err = run_limited("var s = \"ab\";\nwhile (true) s = s + s;", memory.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::OutOfMemory);
+    assert_eq!(
+        err.message,
+        "Out of memory: exceeded the limit of 1048576 bytes."
+    );
+    assert_eq!(err.token.map(|token| token.line), Some(2));
+    let closures =
+        "var f; for (var i = 0; i < 100; i = i + 1) { var s = \"x\" + \"y\"; f = () => s; }";
+    assert!(run_limited(closures, memory.clone()).is_ok());
+
+    // Memory freed at the end of each iteration doesn't add up.
+    let small = Limits {
+        max_memory: Some(4096),
+        ..Limits::default()
+    };
+    let constant = "var n = 0;
+        fun id(x) { var y = x + \"\"; return y; }
+        for (var i = 0; i < 100000; i = i + 1) { var t = id(\"abc\"); n = n + 1; }";
+    assert!(run_limited(constant, small.clone()).is_ok());
+    // Scopes captured by a closure stay in use while the closure may run.
+    let captured = "var fs = nil;
+        for (var i = 0; i < 100000; i = i + 1) { var t = i; fs = () => t; }";
+    let err = run_limited(captured, small.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::OutOfMemory);
+    let growing = "var s = \"\"; for (var i = 0; i < 10000; i = i + 1) { s = s + \"x\"; }";
+    assert_eq!(
+        run_limited(growing, small.clone()).unwrap_err().kind,
+        ErrorKind::OutOfMemory
+    );
+
+    // Only values kept in variables count, so temporaries never add up, whether the
+    // loop body is a block or not.
+    let temporaries = "var s = \"0123456789\"; var i = 0;
+        while (i < 200000) i = i + (s + s + s == \"\" ? 0 : 1);
+        while (i < 400000) { i = i + (s + s + s == \"\" ? 0 : 1); }";
+    assert!(run_limited(temporaries, memory.clone()).is_ok());
+    // Values a block stores in outer variables stay in use after it ends.
+    let kept = "var keep = \"\";
+        for (var i = 0; i < 1000; i = i + 1) { var piece = \"0123456789\"; keep = keep + piece; }";
+    assert_eq!(
+        run_limited(kept, small.clone()).unwrap_err().kind,
+        ErrorKind::OutOfMemory
+    );
+    let replaced = "var keep = \"\";
+        for (var i = 0; i < 1000; i = i + 1) { var piece = \"0123456789\"; keep = piece + piece; }";
+    assert!(run_limited(replaced, small).is_ok());
+
+    let err = run_limited("print -nil;", Limits::default()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Runtime);
+}
+
+#[test]
+fn test_memory_of_globals_lasts_between_runs() {
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    interpreter.set_limits(Limits {
+        max_memory: Some(4096),
+        ..Limits::default()
+    });
+    let mut rlox = RLox::new(interpreter);
+    let string = "x".repeat(1500);
+    rlox.run(&format!("var a = \"{}\";", string));
+    rlox.run(&format!("var b = \"{}\";", string));
+    assert!(!rlox.had_runtime_error);
+    rlox.run(&format!("var c = \"{}\";", string));
+    assert!(rlox.had_runtime_error);
+    // Replacing a global frees its old value.
+    rlox.had_runtime_error = false;
+    rlox.run(&format!("a = nil; var c = \"{}\";", string));
+    assert!(!rlox.had_runtime_error);
+}
+
+/// Runs `source` with the given limits on a thread with the stack the interpreter
+/// needs for its deepest calls.
+fn run_deep(source: &'static str, limits: Limits) -> Result<(), RuntimeError> {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run_limited(source, limits))
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn test_stack_overflow() {
+    let err = run_deep(
+        "fun f(n) {\n  return f(n + 1);\n}\nf(0);",
+        Limits::default(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind, ErrorKind::CallDepth);
+    assert_eq!(err.message, "Stack overflow.");
+    assert_eq!(err.token.map(|token| token.line), Some(2));
+
+    let deep = "fun f(n) { if (n > 0) return 1 + f(n - 1); return 0; } f(1000);";
+    assert!(run_deep(deep, Limits::default()).is_ok());
+
+    // A call depth limit above the safe maximum stops at the maximum instead.
+    let depth = Limits {
+        max_call_depth: Some(5000),
+        ..Limits::default()
+    };
+    let deeper = "fun f(n) { if (n > 0) return 1 + f(n - 1); return 0; } f(4000);";
+    let err = run_deep(deeper, depth).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::CallDepth);
+    assert_eq!(err.message, "Stack overflow.");
+}
+
+#[test]
+fn test_limits_apply_to_each_run() {
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    interpreter.set_limits(Limits {
+        max_statements: Some(3),
+        ..Limits::default()
+    });
+    let mut rlox = RLox::new(interpreter);
+    for _ in 0..3 {
+        rlox.run("print 1; print 2; print 3;");
+    }
+    assert!(!rlox.had_runtime_error);
+    rlox.run("print 1; print 2; print 3; print 4;");
+    assert!(rlox.had_runtime_error);
+}
+
+#[test]
+fn test_cancellation() {
+    let source = "
+        fun spin() { while (true) { var x = 1; } }
+        var y = 0;
+        { var z = 2; spin(); }
+    ";
+    let mut scanner = Scanner::new(source);
+    let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+
+    let token = interpreter.cancellation_token();
+    let canceller = thread::spawn({
+        let token = token.clone();
+        move || {
+            thread::sleep(Duration::from_millis(20));
+            token.cancel();
+        }
+    });
+    let err = interpreter.interpret(&stmts).unwrap_err();
+    canceller.join().unwrap();
+
+    assert!(token.is_cancelled());
+    assert_eq!(err.kind, ErrorKind::Cancelled);
+    assert_eq!(err.message, "Execution was cancelled.");
+    // Only the global scope is left, with the script's globals.
+    let scopes = interpreter.environment.scopes();
+    assert_eq!(scopes.len(), 1);
+    assert!(scopes[0].iter().any(|(name, _)| name == "y"));
+    assert!(!scopes[0].iter().any(|(name, _)| name == "z" || name == "x"));
+
+    // The interpreter runs again after a cancellation, with a fresh token.
+    let mut scanner = Scanner::new("print 1;");
+    let print = Parser::new(scanner.scan_tokens()).parse().unwrap();
+    assert!(interpreter.interpret(&print).is_ok());
+    let token = interpreter.cancellation_token();
+    assert!(!token.is_cancelled());
+    // Cancelling before a run stops that run only.
+    token.cancel();
+    let err = interpreter.interpret(&print).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Cancelled);
+    assert!(interpreter.interpret(&print).is_ok());
+    drop(interpreter);
+    assert_eq!(output, b"1\n1\n");
+}
+
+/// Runs `source` with the given capabilities, returning its output or the error that
+/// stopped it.
+fn run_sandboxed(source: &str, capabilities: Capabilities) -> Result<String, RuntimeError> {
+    let mut scanner = Scanner::new(source);
+    let stmts = Parser::new(scanner.scan_tokens()).parse().unwrap();
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(Environment::default(), &mut output);
+    interpreter.set_capabilities(capabilities);
+    interpreter.interpret(&stmts)?;
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_capabilities() {
+    let test_cases = [
+        ("clock();", Capability::Time, "clock", "time"),
+        ("random();", Capability::Random, "random", "randomness"),
+        (
+            "getenv(\"HOME\");",
+            Capability::Env,
+            "getenv",
+            "environment variables",
+        ),
+        (
+            "read_file(\"x\");",
+            Capability::FileIo,
+            "read_file",
+            "file I/O",
+        ),
+        (
+            "write_file(\"x\", \"\");",
+            Capability::FileIo,
+            "write_file",
+            "file I/O",
+        ),
+        ("read_line();", Capability::Stdin, "read_line", "stdin"),
+    ];
+    for (source, capability, name, description) in test_cases {
+        let err = run_sandboxed(source, Capabilities::all().deny(capability)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::PermissionDenied, "{}", source);
+        assert_eq!(
+            err.message,
+            format!(
+                "Can't call '{}': the {} capability is disabled.",
+                name, description
+            )
+        );
+        assert!(err.token.is_some());
+    }
+
+    // Disabled natives are still defined.
+    assert_eq!(
+        run_sandboxed("print clock;", Capabilities::none()).unwrap(),
+        "<native fn clock>\n"
+    );
+    assert_eq!(
+        run_sandboxed(
+            "var r = random(); print r >= 0 and r < 1;",
+            Capabilities::none().allow(Capability::Random)
+        )
+        .unwrap(),
+        "true\n"
+    );
+}
+
+#[test]
+fn test_io_natives() {
+    let path = env::temp_dir().join(format!("rlox-natives-{}.txt", std::process::id()));
+    let path = path.to_string_lossy().replace('\\', "/");
+    let source = format!(
+        "write_file(\"{0}\", \"hello\"); print read_file(\"{0}\") + \"!\";",
+        path
+    );
+    let file_io = Capabilities::none().allow(Capability::FileIo);
+    assert_eq!(run_sandboxed(&source, file_io.clone()).unwrap(), "hello!\n");
+    fs::remove_file(&path).unwrap();
+
+    let err = run_sandboxed(&format!("read_file(\"{}\");", path), file_io.clone()).unwrap_err();
+    assert_eq!(err.kind, ErrorKind::Runtime);
+    assert!(err.message.starts_with("Could not read"));
+    let err = run_sandboxed("read_file(1);", file_io).unwrap_err();
+    assert_eq!(err.message, "read_file expects a string path, got 1.");
+
+    let env = Capabilities::none().allow(Capability::Env);
+    assert_eq!(
+        run_sandboxed("print getenv(\"RLOX_SURELY_UNSET_VARIABLE\");", env).unwrap(),
+        "nil\n"
+    );
+}
@@ -0,0 +1,307 @@
+use rlox::lsp;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///test.lox";
+
+const SOURCE: &str = "var greeting = \"hi\";
+fun greet(name) {
+  var message = greeting + name;
+  return message;
+}
+print greet(\"lox\");
+";
+
+/// A scripted client: frames each message, runs the server over all of them, and
+/// returns what the server wrote back.
+fn run(messages: &[Value]) -> (bool, Vec<Value>) {
+    let mut input = Vec::new();
+    for message in messages {
+        lsp::write_message(&mut input, message).unwrap();
+    }
+
+    let mut output = Vec::new();
+    let shut_down = lsp::run(&input[..], &mut output).unwrap();
+
+    let mut responses = Vec::new();
+    let mut reader = &output[..];
+    while let Some(message) = lsp::read_message(&mut reader).unwrap() {
+        responses.push(message);
+    }
+    (shut_down, responses)
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn open(text: &str) -> Value {
+    notification(
+        "textDocument/didOpen",
+        json!({ "textDocument": { "uri": URI, "languageId": "lox", "version": 1, "text": text } }),
+    )
+}
+
+fn at(line: u64, character: u64) -> Value {
+    json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+}
+
+fn range(line: u64, start: u64, end: u64) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+/// Runs a session that opens `SOURCE`, sends `request`, then shuts down, and returns
+/// the result of the request.
+fn result_of(method: &str, params: Value) -> Value {
+    let (_, responses) = run(&[
+        request(1, "initialize", json!({})),
+        open(SOURCE),
+        request(2, method, params),
+    ]);
+    responses
+        .into_iter()
+        .find(|response| response["id"] == 2)
+        .expect("no response")["result"]
+        .clone()
+}
+
+#[test]
+fn test_lifecycle() {
+    let (shut_down, responses) = run(&[
+        request(1, "initialize", json!({ "capabilities": {} })),
+        notification("initialized", json!({})),
+        request(2, "workspace/unknown", json!({})),
+        request(3, "shutdown", Value::Null),
+        request(4, "textDocument/hover", at(0, 0)),
+        notification("exit", Value::Null),
+        request(5, "shutdown", Value::Null),
+    ]);
+
+    assert!(shut_down);
+    assert_eq!(responses.len(), 4);
+    let capabilities = &responses[0]["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(responses[1]["error"]["code"], -32601);
+    assert_eq!(
+        responses[2],
+        json!({ "jsonrpc": "2.0", "id": 3, "result": null })
+    );
+    assert_eq!(responses[3]["error"]["code"], -32600);
+
+    let (shut_down, _) = run(&[notification("exit", Value::Null)]);
+    assert!(!shut_down);
+}
+
+#[test]
+fn test_diagnostics() {
+    let change = notification(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "print missing;\n{ var unused = 1; }\n" }],
+        }),
+    );
+    let (_, responses) = run(&[
+        open("var x = 1;\nprint (x;\n"),
+        change,
+        notification(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": URI } }),
+        ),
+    ]);
+
+    let diagnostics: Vec<&Value> = responses
+        .iter()
+        .map(|notification| {
+            assert_eq!(notification["method"], "textDocument/publishDiagnostics");
+            assert_eq!(notification["params"]["uri"], URI);
+            &notification["params"]["diagnostics"]
+        })
+        .collect();
+
+    assert_eq!(
+        diagnostics[0],
+        &json!([{
+            "range": range(1, 8, 9),
+            "severity": 1,
+            "source": "rlox",
+            "message": "Expect ')' after expression.",
+        }])
+    );
+    assert_eq!(
+        diagnostics[1],
+        &json!([
+            {
+                "range": range(0, 6, 13),
+                "severity": 1,
+                "source": "rlox",
+                "message": "Undefined variable 'missing'.",
+            },
+            {
+                "range": range(1, 0, 19),
+                "severity": 2,
+                "source": "rlox",
+                "code": "unused-variable",
+                "message": "'unused' is declared but never used.",
+            },
+        ])
+    );
+    assert_eq!(diagnostics[2], &json!([]));
+}
+
+#[test]
+fn test_definition() {
+    // `greeting` used inside `greet`.
+    assert_eq!(
+        result_of("textDocument/definition", at(2, 18)),
+        json!({ "uri": URI, "range": range(0, 4, 12) })
+    );
+    // The `name` parameter.
+    assert_eq!(
+        result_of("textDocument/definition", at(2, 30)),
+        json!({ "uri": URI, "range": range(1, 10, 14) })
+    );
+    // Nothing to resolve on a keyword.
+    assert_eq!(result_of("textDocument/definition", at(5, 1)), Value::Null);
+}
+
+#[test]
+fn test_references() {
+    let mut params = at(3, 10);
+    params["context"] = json!({ "includeDeclaration": true });
+    assert_eq!(
+        result_of("textDocument/references", params.clone()),
+        json!([
+            { "uri": URI, "range": range(2, 6, 13) },
+            { "uri": URI, "range": range(3, 9, 16) },
+        ])
+    );
+
+    params["context"]["includeDeclaration"] = json!(false);
+    assert_eq!(
+        result_of("textDocument/references", params),
+        json!([{ "uri": URI, "range": range(3, 9, 16) }])
+    );
+}
+
+#[test]
+fn test_compound_assignment() {
+    let (_, responses) = run(&[
+        request(1, "initialize", json!({})),
+        open("var x = 1;\nx += 2;\ny -= x;\n"),
+        request(2, "textDocument/references", at(1, 0)),
+    ]);
+
+    assert_eq!(
+        responses[1]["params"]["diagnostics"],
+        json!([{
+            "range": range(2, 0, 1),
+            "severity": 1,
+            "source": "rlox",
+            "message": "Undefined variable 'y'.",
+        }])
+    );
+    assert_eq!(
+        responses[2]["result"],
+        json!([
+            { "uri": URI, "range": range(0, 4, 5) },
+            { "uri": URI, "range": range(1, 0, 1) },
+            { "uri": URI, "range": range(2, 5, 6) },
+        ])
+    );
+}
+
+#[test]
+fn test_non_ascii_text() {
+    // Positions count UTF-16 code units: `é` is one and `𝄞` two, though both take
+    // more bytes in the source.
+    let (_, responses) = run(&[
+        request(1, "initialize", json!({})),
+        open("var word = \"naïve\"; // déjà vu\nprint \"𝄞\" + word + missing;\n"),
+        request(2, "textDocument/hover", at(1, 14)),
+        open("print \"é\"; 𝄞\n"),
+    ]);
+
+    assert_eq!(
+        responses[1]["params"]["diagnostics"],
+        json!([{
+            "range": range(1, 20, 27),
+            "severity": 1,
+            "source": "rlox",
+            "message": "Undefined variable 'missing'.",
+        }])
+    );
+    assert_eq!(
+        responses[2]["result"]["contents"]["value"],
+        "```lox\nvar word = \"naïve\"\n```\nDeclared on line 1."
+    );
+    assert_eq!(
+        responses[3]["params"]["diagnostics"],
+        json!([{
+            "range": range(0, 11, 13),
+            "severity": 1,
+            "source": "rlox",
+            "message": "Unexpected character",
+        }])
+    );
+}
+
+#[test]
+fn test_hover() {
+    assert_eq!(
+        result_of("textDocument/hover", at(5, 8)),
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": "```lox\nfun greet(name)\n```\nDeclared on line 2.",
+            },
+        })
+    );
+    assert_eq!(
+        result_of("textDocument/hover", at(3, 12))["contents"]["value"],
+        "```lox\nvar message = greeting + name\n```\nDeclared on line 3."
+    );
+}
+
+#[test]
+fn test_document_symbols() {
+    let symbols = result_of(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    assert_eq!(
+        symbols,
+        json!([
+            {
+                "name": "greeting",
+                "detail": "var greeting = \"hi\"",
+                "kind": 13,
+                "range": range(0, 4, 12),
+                "selectionRange": range(0, 4, 12),
+                "children": [],
+            },
+            {
+                "name": "greet",
+                "detail": "fun greet(name)",
+                "kind": 12,
+                "range": range(1, 4, 9),
+                "selectionRange": range(1, 4, 9),
+                "children": [{
+                    "name": "message",
+                    "detail": "var message = greeting + name",
+                    "kind": 13,
+                    "range": range(2, 6, 13),
+                    "selectionRange": range(2, 6, 13),
+                    "children": [],
+                }],
+            },
+        ])
+    );
+}